@@ -18,10 +18,18 @@ impl Bitstream {
         Bitstream { pos: (0..0), data: vec![0; 8] }
     }
 
+    pub fn len(&self) -> usize {
+        self.pos.end - self.pos.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos.start == self.pos.end
+    }
+
     #[cfg_attr(feature = "clippy", allow(inline_always))]
     #[inline(always)]
     fn get_indices(pos: usize) -> (usize, usize) {
-        let idx = (pos >> 3) as usize;
+        let idx = pos >> 3;
         let bitidx = pos & 0x07;
 
         (idx, bitidx)
@@ -29,7 +37,7 @@ impl Bitstream {
 
     fn extend(&mut self) {
         let add_len = self.data.len() / 2;
-        self.data.extend(iter::repeat(0).take(add_len));
+        self.data.extend(iter::repeat_n(0, add_len));
     }
 
     pub fn append(&mut self, val: u8) {
@@ -81,30 +89,23 @@ impl Bitstream {
     }
 
     fn ceil_div(num: usize, denom: usize) -> usize {
-        (num + denom - 1) / denom
+        num.div_ceil(denom)
     }
 
-    pub fn write(&self, writer: &mut Write) -> io::Result<usize> {
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<usize> {
         // FIXME: make this less terrible
         let byte_len = Bitstream::ceil_div(self.pos.end, 8);
 
         // TODO: handle this
         assert_eq!(self.pos.start, 0);
 
-        let bytes_out =
-            match writer.write_u32::<BigEndian>(self.pos.end as u32) {
-                Err(err) => return Err(err),
-                Ok(_) => 4,
-            }
-            +
-            match writer.write(&self.data[0..byte_len]) {
-                Err(err) => return Err(err),
-                Ok(nb) => nb,
-            };
-        Ok(bytes_out)
+        writer.write_u32::<BigEndian>(self.pos.end as u32)?;
+        writer.write_all(&self.data[0..byte_len])?;
+
+        Ok(4 + byte_len)
     }
 
-    pub fn read(reader: &mut Read) -> io::Result<Option<Bitstream>> {
+    pub fn read(reader: &mut dyn Read) -> io::Result<Option<Bitstream>> {
         let pos = match reader.read_u32::<BigEndian>() {
             Err(_) => return Ok(None),        // FIXME: handle non-EOF
             Ok(pos) => pos as usize,
@@ -123,6 +124,12 @@ impl Bitstream {
     }
 }
 
+impl Default for Bitstream {
+    fn default() -> Bitstream {
+        Bitstream::new()
+    }
+}
+
 impl Add for Bitstream {
     type Output = Bitstream;
 
@@ -167,6 +174,6 @@ impl Display for Bitstream {
 
 impl Debug for Bitstream {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        (self as &Display).fmt(f)
+        (self as &dyn Display).fmt(f)
     }
 }
@@ -0,0 +1,144 @@
+// The bijective BWT of Gil and Scott, which needs no primary index.  The block is split into its
+// Lyndon factorization, a non-increasing run of Lyndon words, and the rotations of all the words
+// are sorted together, each compared as if repeated forever.  The output is the byte before each
+// rotation within its own word.
+//
+// Undoing it, the rows fall into cycles under the usual next-row mapping, one per word.  Taking
+// the cycles in order of their first row gives the words smallest first, so they're put back
+// together the other way round.
+use bwt::inverse::first_rows;
+use bwt::doubling::sort_by_doubling;
+
+// Duval's algorithm: the start of each word in the Lyndon factorization of `data`.
+fn lyndon_factors(data: &[u8]) -> Vec<usize> {
+    let n = data.len();
+    let mut starts = Vec::new();
+    let mut i = 0;
+
+    while i < n {
+        let (mut j, mut k) = (i + 1, i);
+        while j < n && data[k] <= data[j] {
+            k = if data[k] < data[j] { i } else { k + 1 };
+            j += 1;
+        };
+
+        while i <= k {
+            starts.push(i);
+            i += j - k;
+        };
+    };
+
+    starts
+}
+
+/// The bijective BWT of `data`, which is exactly as long as `data`.
+pub fn encode_bijective(data: &[u8]) -> Vec<u8> {
+    let n = data.len();
+
+    // For each position, where its word starts and how long it is.
+    let mut words = vec![(0, 0); n];
+    let mut starts = lyndon_factors(data);
+    starts.push(n);
+    for pair in starts.windows(2) {
+        for word in &mut words[pair[0]..pair[1]] {
+            *word = (pair[0], pair[1] - pair[0]);
+        };
+    };
+
+    let rotations = sort_by_doubling(data, |i, h| {
+        let (start, len) = words[i];
+        Some(start + (i - start + h) % len)
+    });
+
+    rotations.iter().
+        map(|&i| {
+            let (start, len) = words[i as usize];
+            data[start + (i as usize - start + len - 1) % len]
+        }).
+        collect()
+}
+
+pub fn decode_bijective(buf: &[u8]) -> Vec<u8> {
+    let len = buf.len();
+    let mut starts = first_rows(buf);
+
+    let mut next = vec![0u32; len];
+    for (i, &c) in buf.iter().enumerate() {
+        next[starts[c as usize]] = i as u32;
+        starts[c as usize] += 1;
+    };
+
+    let mut words = Vec::new();
+    let mut seen = vec![false; len];
+    for first in 0..len {
+        if seen[first] {
+            continue;
+        };
+
+        let mut word = Vec::new();
+        let mut row = first;
+        loop {
+            seen[row] = true;
+            row = next[row] as usize;
+            word.push(buf[row]);
+
+            if row == first {
+                break;
+            };
+        };
+        words.push(word);
+    };
+
+    words.iter().rev().flatten().cloned().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use bwt::bijective::lyndon_factors;
+    use huffman;
+    use mtf;
+    use rle;
+    use testdata;
+
+    #[test]
+    fn factors() {
+        assert_eq!(lyndon_factors(b"banana"), vec![0, 1, 3, 5]);
+        assert_eq!(lyndon_factors(b"abab"), vec![0, 2]);
+        assert_eq!(lyndon_factors(b"aab"), vec![0]);
+        assert_eq!(lyndon_factors(b""), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 3] = [b"cbacba", &[9; 100], include_bytes!("bijective.rs")];
+
+        for &input in testdata::SAMPLES.iter().chain(inputs.iter()) {
+            let encoded = bwt::encode_bijective(input);
+
+            assert_eq!(encoded.len(), input.len());
+            assert_eq!(bwt::decode_bijective(&encoded), input);
+        };
+
+        // Factors as "S" and "COTTY"; "S" sorts between "OTTYC" and "TTYCO".
+        assert_eq!(bwt::encode_bijective(b"SCOTTY"), b"YCSOTT");
+    }
+
+    #[test]
+    fn ratio_against_classic() {
+        let data = testdata::sources();
+        let compressed_len = |transformed: &[u8]| {
+            let mut buf = Vec::new();
+            let rled = rle::encode(&mtf::encode(transformed));
+            huffman::encode(&rled).unwrap().write(&mut buf).unwrap();
+            buf.len()
+        };
+
+        let classic = compressed_len(&bwt::encode(&data));
+        let bijective = compressed_len(&bwt::encode_bijective(&data));
+
+        // Within 1% of the classic transform, which it differs from only near the ends of the
+        // Lyndon factors.
+        assert!(bijective <= classic + classic / 100);
+    }
+}
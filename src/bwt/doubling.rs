@@ -0,0 +1,100 @@
+// Sorting by prefix doubling.  Positions start out bucketed by their first byte.  Each round
+// sorts every bucket that still holds more than one position by the rank of the position `h`
+// steps on, which orders them by their first `2h` bytes, and splits the buckets where the ranks
+// differ.  Slower than SA-IS, but it orders anything that can say where each position's
+// successor is, such as the rotations of several words at once.
+use std::ops::Range;
+
+/// Sorts the positions of `text` by the sequence of bytes read by following `successor`, where
+/// `successor(i, h)` is the position `h` steps on from `i`, if there is one.  Positions whose
+/// sequences agree for `text.len()` steps are left in any order.
+pub fn sort_by_doubling<S>(text: &[u8], successor: S) -> Vec<u32>
+    where S: Fn(usize, usize) -> Option<usize> {
+    let n = text.len();
+
+    let mut counts = [0; 257];
+    for &c in text {
+        counts[c as usize + 1] += 1;
+    };
+    for c in 0..256 {
+        counts[c + 1] += counts[c];
+    };
+
+    // A suffix's rank is where its bucket starts.
+    let mut sa = vec![0; n];
+    let mut rank = vec![0; n];
+    let mut next = counts;
+    for (i, &c) in text.iter().enumerate() {
+        sa[next[c as usize]] = i as u32;
+        rank[i] = counts[c as usize] as u32;
+        next[c as usize] += 1;
+    };
+
+    let mut buckets = (0..256).
+        map(|c| counts[c]..counts[c + 1]).
+        filter(|bucket| bucket.len() > 1).
+        collect::<Vec<Range<usize>>>();
+
+    let mut h = 1;
+    while !buckets.is_empty() && h < n {
+        // A position with no successor that far has no rank there, and goes first.
+        let key = |i: u32| successor(i as usize, h).map(|j| rank[j]);
+        for bucket in &buckets {
+            sa[bucket.clone()].sort_unstable_by_key(|&i| key(i));
+        };
+
+        // Split the buckets where the ranks `h` on differ.  All the new ranks are worked out
+        // before any are stored, since the old ones are what the keys are made of.
+        let mut split = Vec::with_capacity(buckets.len());
+        for bucket in &buckets {
+            let mut start = bucket.start;
+            for j in bucket.start + 1..bucket.end {
+                if key(sa[j]) != key(sa[j - 1]) {
+                    split.push(start..j);
+                    start = j;
+                };
+            };
+            split.push(start..bucket.end);
+        };
+
+        for bucket in &split {
+            for &i in &sa[bucket.clone()] {
+                rank[i as usize] = bucket.start as u32;
+            };
+        };
+
+        buckets = split.into_iter().filter(|bucket| bucket.len() > 1).collect();
+        h *= 2;
+    };
+
+    sa
+}
+
+#[cfg(test)]
+mod test {
+    use bwt::doubling::sort_by_doubling;
+    use bwt::sais::sais;
+    use testdata::Random;
+
+    #[test]
+    fn matches_sais() {
+        let mut random = Random::new(3);
+
+        let mut inputs: Vec<Vec<u8>> = vec![
+            b"a".to_vec(), b"mississippi".to_vec(), vec![0; 20_000], b"abc".repeat(7000),
+            include_bytes!("doubling.rs").repeat(3),
+        ];
+        for &alphabet in &[2, 256] {
+            inputs.push(random.bytes(30_000, alphabet));
+        };
+
+        for text in &inputs {
+            let mut expected = vec![0; text.len()];
+            sais(&text[..], &mut expected, 256);
+
+            let n = text.len();
+            let suffixes = sort_by_doubling(text, |i, h| if i + h < n { Some(i + h) } else { None });
+            assert_eq!(suffixes, expected);
+        };
+    }
+}
@@ -0,0 +1,403 @@
+// An FM-index over the output of `bwt::encode`, for finding a pattern in a block without undoing
+// the BWT.  Searching backwards through the pattern narrows down the range of rows that start
+// with it, using how often each byte occurs in the last column before a given row.  Those counts
+// are kept for every `OCC_RATE`th row and finished off by scanning the last column.
+//
+// Finding where a row's rotation starts means walking back along the LF mapping, one byte of
+// text per step, until reaching a row whose position was sampled.
+//
+// The rows are rotations, so a match can run off the end of the block and back to its start.
+// Those are dropped.  A block that repeats some shorter string has several rows for each distinct
+// rotation; positions are then worked out within one period and copied to the others.
+use bwt::inverse::read_header;
+use std::io;
+use std::io::Write;
+use std::io::Read;
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
+
+const OCC_RATE: usize = 256;
+
+/// The rows of the rotations starting every `rate` bytes into a block, as taken by
+/// `bwt::encode_sampled`.  Positions are counted within `period`, the shortest string the block
+/// repeats, which is usually the whole block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SampledSa {
+    pub rate: usize,
+    pub period: usize,
+    pub rows: Vec<u32>,
+}
+
+impl SampledSa {
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        writer.write_u32::<BigEndian>(self.rate as u32)?;
+        writer.write_u32::<BigEndian>(self.period as u32)?;
+        writer.write_u32::<BigEndian>(self.rows.len() as u32)?;
+        for &row in &self.rows {
+            writer.write_u32::<BigEndian>(row)?;
+        };
+
+        Ok(12 + 4 * self.rows.len())
+    }
+
+    /// Reads samples written by `write`.  They can only be checked against their block by
+    /// `FmIndex::with_samples`, so until then the count isn't trusted: the rows are read as
+    /// they come rather than room made for them up front.
+    pub fn read(reader: &mut dyn Read) -> io::Result<SampledSa> {
+        let rate = reader.read_u32::<BigEndian>()? as usize;
+        let period = reader.read_u32::<BigEndian>()? as usize;
+        let count = reader.read_u32::<BigEndian>()? as usize;
+
+        if rate == 0 || count != period.div_ceil(rate) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad suffix array samples"));
+        };
+
+        let mut bytes = Vec::new();
+        Read::take(&mut *reader, 4 * count as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != 4 * count {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                      "Truncated suffix array samples"));
+        };
+        let rows = bytes.chunks(4).map(BigEndian::read_u32).collect();
+
+        Ok(SampledSa { rate, period, rows })
+    }
+
+    // Whether these could be the samples of a block of `len` rows.
+    fn fits(&self, len: usize) -> bool {
+        let period_fits = if len == 0 {
+            self.period == 0
+        } else {
+            self.period > 0 && len.is_multiple_of(self.period)
+        };
+
+        period_fits && self.rate > 0 && self.rows.len() == self.period.div_ceil(self.rate) &&
+            self.rows.iter().all(|&row| (row as usize) < len)
+    }
+}
+
+pub struct FmIndex {
+    last: Vec<u8>,
+    primary: usize,
+    // The first row starting with each byte.
+    starts: [usize; 256],
+    // How often each byte occurs in the last column before every `OCC_RATE`th row.
+    occ: Vec<[u32; 256]>,
+    period: usize,
+    repeats: usize,
+    rate: usize,
+    // The sampled rows by position, and the same rows with their positions sorted by row.
+    sampled_rows: Vec<u32>,
+    samples: Vec<(u32, u32)>,
+}
+
+impl FmIndex {
+    /// Indexes `bwt`, the output of `bwt::encode` or its variants, sampling every `rate`th
+    /// position.  Sampling walks the whole block, which `with_samples` avoids.
+    pub fn new(bwt: &[u8], rate: usize) -> io::Result<FmIndex> {
+        let mut index = FmIndex::build(bwt)?;
+        let len = index.last.len();
+        let rate = rate.max(1);
+
+        if len == 0 {
+            return Ok(index);
+        };
+
+        let mut lf = vec![0u32; len];
+        let mut seen = [0; 256];
+        for (row, &c) in index.last.iter().enumerate() {
+            lf[row] = (index.starts[c as usize] + seen[c as usize]) as u32;
+            seen[c as usize] += 1;
+        };
+
+        // Stepping back from the primary row returns to it after one period.
+        let mut period: usize = 1;
+        let mut row = lf[index.primary] as usize;
+        while row != index.primary {
+            row = lf[row] as usize;
+            period += 1;
+        };
+        if !len.is_multiple_of(period) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "BWT block doesn't repeat its primary row's period"));
+        };
+
+        let mut samples = vec![0; period.div_ceil(rate)];
+        for pos in (0..period).rev() {
+            row = lf[row] as usize;
+            if pos.is_multiple_of(rate) {
+                samples[pos / rate] = row as u32;
+            };
+        };
+
+        index.set_samples(&SampledSa { rate, period, rows: samples });
+        Ok(index)
+    }
+
+    /// Indexes `bwt` using samples stored alongside it.
+    pub fn with_samples(bwt: &[u8], samples: &SampledSa) -> io::Result<FmIndex> {
+        let mut index = FmIndex::build(bwt)?;
+        if !samples.fits(index.len()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "Suffix array samples don't match the block"));
+        };
+        index.set_samples(samples);
+        Ok(index)
+    }
+
+    fn build(bwt: &[u8]) -> io::Result<FmIndex> {
+        let (rows, _, last) = read_header(bwt)?;
+
+        let mut occ = Vec::with_capacity(last.len() / OCC_RATE + 1);
+        let mut counts = [0; 256];
+        for (row, &c) in last.iter().enumerate() {
+            if row.is_multiple_of(OCC_RATE) {
+                occ.push(counts);
+            };
+            counts[c as usize] += 1;
+        };
+        occ.push(counts);
+
+        let mut starts = [0; 256];
+        for c in 1..256 {
+            starts[c] = starts[c - 1] + counts[c - 1] as usize;
+        };
+
+        Ok(FmIndex {
+            last: last.to_vec(),
+            primary: rows[0],
+            starts,
+            occ,
+            period: 0,
+            repeats: 0,
+            rate: 1,
+            sampled_rows: Vec::new(),
+            samples: Vec::new(),
+        })
+    }
+
+    fn set_samples(&mut self, samples: &SampledSa) {
+        self.period = samples.period;
+        self.repeats = self.last.len().checked_div(samples.period).unwrap_or(0);
+        self.rate = samples.rate;
+        self.sampled_rows = samples.rows.clone();
+        self.samples = samples.rows.iter().
+            enumerate().
+            map(|(i, &row)| (row, (i * samples.rate) as u32)).
+            collect();
+        self.samples.sort_unstable();
+    }
+
+    /// The length of the indexed block.
+    pub fn len(&self) -> usize {
+        self.last.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last.is_empty()
+    }
+
+    // How often `c` occurs in the last column before `row`.
+    fn rank(&self, c: u8, row: usize) -> usize {
+        let from = row - row % OCC_RATE;
+        let scanned = self.last[from..row].iter().filter(|&&b| b == c).count();
+
+        self.occ[row / OCC_RATE][c as usize] as usize + scanned
+    }
+
+    // The row of the rotation starting one byte before `row`'s.
+    fn lf(&self, row: usize) -> usize {
+        let c = self.last[row];
+        self.starts[c as usize] + self.rank(c, row)
+    }
+
+    // The rows starting with `pattern`, wrapping around the end of the block included.
+    fn rows(&self, pattern: &[u8]) -> (usize, usize) {
+        let (mut lo, mut hi) = (0, self.len());
+
+        for &c in pattern.iter().rev() {
+            lo = self.starts[c as usize] + self.rank(c, lo);
+            hi = self.starts[c as usize] + self.rank(c, hi);
+            if lo >= hi {
+                return (0, 0);
+            };
+        };
+
+        (lo, hi)
+    }
+
+    /// How many times `pattern` occurs in the block.
+    pub fn count(&self, pattern: &[u8]) -> usize {
+        if pattern.len() > self.len() {
+            return 0;
+        };
+
+        let (lo, hi) = self.rows(pattern);
+        if lo == hi {
+            return 0;
+        };
+
+        // Take off the matches starting in the last `pattern.len() - 1` bytes, which wrap.
+        let mut row = self.primary;
+        let mut wrapped = 0;
+        for _ in 1..pattern.len() {
+            row = self.lf(row);
+            if (lo..hi).contains(&row) {
+                wrapped += 1;
+            };
+        };
+
+        hi - lo - wrapped
+    }
+
+    /// Where `pattern` occurs in the block, in order.  Fails if a row never leads back to a
+    /// sample, which only a corrupt block can do.
+    pub fn locate(&self, pattern: &[u8]) -> io::Result<Vec<usize>> {
+        let len = self.len();
+        if pattern.len() > len {
+            return Ok(Vec::new());
+        };
+
+        let (lo, hi) = self.rows(pattern);
+        let mut found = Vec::with_capacity(hi - lo);
+
+        // Rows for the same rotation are next to each other, and only the ones in step with the
+        // primary row lead back to a sample.
+        for row in (lo..hi).filter(|&row| row % self.repeats == self.primary % self.repeats) {
+            let pos = self.position(row).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "BWT row never reaches a sample")
+            })?;
+
+            for copy in 0..self.repeats {
+                let pos = pos + copy * self.period;
+                if pos + pattern.len() <= len {
+                    found.push(pos);
+                };
+            };
+        };
+
+        found.sort_unstable();
+        Ok(found)
+    }
+
+    /// The bytes of the block from `start` to `end`, found by walking back from the nearest
+    /// sample after `end`.
+    pub fn extract(&self, start: usize, end: usize) -> Vec<u8> {
+        assert!(start <= end && end <= self.len());
+
+        let mut row = self.row_at(end);
+        let mut bytes = vec![0; end - start];
+        for byte in bytes.iter_mut().rev() {
+            *byte = self.last[row];
+            row = self.lf(row);
+        };
+
+        bytes
+    }
+
+    // The row of the rotation starting at `pos`, or of one equal to it.  The end of the block
+    // counts as its start.
+    fn row_at(&self, pos: usize) -> usize {
+        let pos = pos % self.period.max(1);
+        let sample = pos.div_ceil(self.rate) * self.rate;
+
+        let mut row = match self.sampled_rows.get(sample / self.rate) {
+            Some(&row) if sample < self.period => row as usize,
+            _ => self.primary,
+        };
+        let end = sample.min(self.period);
+        for _ in pos..end {
+            row = self.lf(row);
+        };
+
+        row
+    }
+
+    // Where the rotation in `row` starts, within one period.  Every position in a period is at
+    // most a period from a sample, so a row that takes longer is on some other cycle of a
+    // corrupt LF mapping, and would never get there.
+    fn position(&self, mut row: usize) -> Option<usize> {
+        for steps in 0..self.period {
+            if let Ok(i) = self.samples.binary_search_by_key(&(row as u32), |&(row, _)| row) {
+                return Some((self.samples[i].1 as usize + steps) % self.period);
+            };
+
+            row = self.lf(row);
+        };
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use bwt::{FmIndex, SampledSa};
+
+    fn naive(data: &[u8], pattern: &[u8]) -> Vec<usize> {
+        (0..(data.len() + 1).saturating_sub(pattern.len())).
+            filter(|&i| &data[i..i + pattern.len()] == pattern).
+            collect()
+    }
+
+    #[test]
+    fn matches_naive_search() {
+        let inputs: [&[u8]; 5] = [b"abracadabra", b"aaaaaaaa", b"abcabcabcabc", b"a",
+                                  include_bytes!("fm_index.rs")];
+        let patterns: [&[u8]; 9] = [b"a", b"ab", b"abra", b"aaa", b"cab", b"bca", b"z",
+                                    b"self.", b"fn "];
+
+        for &data in inputs.iter() {
+            let (encoded, samples) = bwt::encode_sampled(data, 0, 5);
+            let stored = FmIndex::with_samples(&encoded, &samples).unwrap();
+            let walked = FmIndex::new(&encoded, 3).unwrap();
+
+            for &pattern in patterns.iter() {
+                let expected = naive(data, pattern);
+
+                for index in &[&stored, &walked] {
+                    assert_eq!(index.count(pattern), expected.len());
+                    assert_eq!(index.locate(pattern).unwrap(), expected);
+                };
+            };
+
+            for &(start, end) in &[(0, data.len()), (0, 1), (data.len() / 2, data.len()),
+                                   (1, data.len() / 3 + 1)] {
+                assert_eq!(stored.extract(start, end), &data[start..end]);
+                assert_eq!(walked.extract(start, end), &data[start..end]);
+            };
+        };
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let (encoded, samples) = bwt::encode_sampled(b"mississippi", 2, 4);
+        let mut buf = Vec::new();
+        assert_eq!(samples.write(&mut buf).unwrap(), buf.len());
+
+        let read = SampledSa::read(&mut &buf[..]).unwrap();
+        assert_eq!(read, samples);
+        assert_eq!(FmIndex::with_samples(&encoded, &read).unwrap().locate(b"ss").unwrap(),
+                   vec![2, 5]);
+
+        // A count far beyond what follows, and samples from a different block.
+        let huge = [0, 0, 0, 1, 0x7f, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 0, 0, 0, 0];
+        assert!(SampledSa::read(&mut &huge[..]).is_err());
+        let (other, _) = bwt::encode_sampled(b"mississippi river", 0, 4);
+        assert!(FmIndex::with_samples(&other, &read).is_err());
+    }
+
+    #[test]
+    fn corrupt_cycles() {
+        // Row 3 of "abab" maps to itself, away from the primary row's cycle and its samples.
+        let index = FmIndex::new(&[0, 0, 0, 1, b'a', b'b', b'a', b'b'], 1).unwrap();
+        assert!(index.locate(b"b").is_err());
+
+        let samples = SampledSa { rate: 1, period: 4, rows: vec![1, 0, 1, 0] };
+        let index = FmIndex::with_samples(&[0, 0, 0, 1, b'a', b'b', b'a', b'b'], &samples).
+            unwrap();
+        assert!(index.locate(b"b").is_err());
+
+        // The primary row's cycle is three rows long, which doesn't divide the block.
+        assert!(FmIndex::new(&[0, 0, 0, 1, b'a', b'b', b'a', b'a'], 1).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// Undoing the BWT.  Row `j` of the sorted rotations, shifted left by one, is row `next[j]`, where
+// `next` maps the `k`th occurrence of a byte in the first column to its `k`th occurrence in the
+// last.  Starting from the row holding the original block, following `next` spells the block out
+// front to back, one last-column byte per step.
+//
+// `next` is the only big table.  It takes four bytes a row as a `u32`, or just enough bits to
+// hold a row number when memory matters more than speed.
+use bwt::ENTRIES;
+use std::io;
+use std::thread;
+use byteorder::{BigEndian, ByteOrder};
+
+/// How to trade memory for speed when undoing the BWT.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DecodeMode {
+    /// Five bytes per byte of block: the block and a `u32` per row.
+    #[default]
+    Fast,
+    /// The block and just enough bits per row to hold a row number, about 3.5 bytes per byte
+    /// for a 900k block.  Slower, as every step unpacks bits.
+    Small,
+}
+
+// Fixed-width integers packed end to end.
+struct Packed {
+    width: usize,
+    words: Vec<u64>,
+}
+
+impl Packed {
+    // Enough room for `len` values below `len`.
+    fn new(len: usize) -> Packed {
+        let width = (usize::BITS - (len.saturating_sub(1)).leading_zeros()).max(1) as usize;
+        Packed { width, words: vec![0; (len * width).div_ceil(64) + 1] }
+    }
+
+    fn get(&self, i: usize) -> usize {
+        let bit = i * self.width;
+        let (word, shift) = (bit >> 6, bit & 63);
+        let pair = self.words[word] as u128 | (self.words[word + 1] as u128) << 64;
+
+        ((pair >> shift) as usize) & ((1 << self.width) - 1)
+    }
+
+    fn set(&mut self, i: usize, val: usize) {
+        let bit = i * self.width;
+        let (word, shift) = (bit >> 6, bit & 63);
+        let val = (val as u128) << shift;
+
+        self.words[word] |= val as u64;
+        self.words[word + 1] |= (val >> 64) as u64;
+    }
+}
+
+// Where each byte's run of rows starts in the first column.
+pub(super) fn first_rows(data: &[u8]) -> [usize; 256] {
+    let mut counts = [0; 256];
+    for &c in data {
+        counts[c as usize] += 1;
+    };
+
+    let mut starts = [0; 256];
+    for c in 1..256 {
+        starts[c] = starts[c - 1] + counts[c - 1];
+    };
+    starts
+}
+
+// Anything that maps a row to the next.
+trait Next: Sync {
+    fn next(&self, row: usize) -> usize;
+}
+
+impl Next for Vec<u32> {
+    fn next(&self, row: usize) -> usize {
+        self[row] as usize
+    }
+}
+
+impl Next for Packed {
+    fn next(&self, row: usize) -> usize {
+        self.get(row)
+    }
+}
+
+// Spells out `interval` bytes from each of `rows` into consecutive stretches of `out`.  The chains
+// are walked in step, so their cache misses overlap instead of waiting on each other.
+fn walk<N: Next>(next: &N, data: &[u8], rows: &[usize], interval: usize, out: &mut [u8]) {
+    let mut rows = rows.to_vec();
+
+    for step in 0..interval {
+        for (chain, row) in rows.iter_mut().enumerate() {
+            let pos = chain * interval + step;
+            if pos < out.len() {
+                *row = next.next(*row);
+                out[pos] = data[*row];
+            };
+        };
+    };
+}
+
+fn walk_parallel<N: Next>(next: &N, data: &[u8], rows: &[usize], interval: usize,
+                          threads: usize) -> Vec<u8> {
+    let mut out = vec![0; data.len()];
+    let per_thread = rows.len().div_ceil(threads.max(1));
+
+    if per_thread == rows.len() {
+        walk(next, data, rows, interval, &mut out);
+    } else {
+        thread::scope(|scope| {
+            for (rows, out) in rows.chunks(per_thread).zip(out.chunks_mut(per_thread * interval)) {
+                scope.spawn(move || walk(next, data, rows, interval, out));
+            };
+        });
+    };
+
+    out
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// The rows to start from, the number of bytes to decode from each, and the last column.
+pub(super) fn read_header(buf: &[u8]) -> io::Result<(Vec<usize>, usize, &[u8])> {
+    if buf.len() < 4 {
+        return Err(invalid("BWT block is too short for its index"));
+    };
+    let idx = BigEndian::read_u32(&buf[0..4]);
+
+    // An empty block is just an index of 0.
+    if idx & ENTRIES == 0 {
+        let data = &buf[4..];
+        if idx as usize >= data.len().max(1) {
+            return Err(invalid("BWT index is past the end of the block"));
+        };
+        return Ok((vec![idx as usize], data.len().max(1), data));
+    };
+
+    let count = match buf.get(8..12) {
+        Some(count) => BigEndian::read_u32(count) as usize,
+        None => return Err(invalid("BWT block is too short for its entry points")),
+    };
+    let header_len = 12usize.saturating_add(count.saturating_mul(4));
+    if buf.len() < header_len {
+        return Err(invalid("BWT block is too short for its entry points"));
+    };
+    let interval = BigEndian::read_u32(&buf[4..8]) as usize;
+    let data = &buf[header_len..];
+
+    let mut rows = vec![(idx & !ENTRIES) as usize];
+    rows.extend(buf[12..header_len].chunks(4).map(|row| BigEndian::read_u32(row) as usize));
+
+    // The entry points must cover the block, one `interval` each.
+    if interval == 0 || rows.len() != data.len().div_ceil(interval) {
+        return Err(invalid("BWT entry points don't match the block"));
+    };
+    if rows.iter().any(|&row| row >= data.len()) {
+        return Err(invalid("BWT index is past the end of the block"));
+    };
+
+    Ok((rows, interval, data))
+}
+
+pub fn decode_with(buf: &[u8], mode: DecodeMode) -> io::Result<Vec<u8>> {
+    decode_parallel(buf, mode, 1)
+}
+
+/// Like `decode_with`, but when the block has extra entry points, spreads them over up to
+/// `threads` threads.
+pub fn decode_parallel(buf: &[u8], mode: DecodeMode, threads: usize) -> io::Result<Vec<u8>> {
+    let (rows, interval, data) = read_header(buf)?;
+    let len = data.len();
+    let mut starts = first_rows(data);
+
+    match mode {
+        DecodeMode::Fast => {
+            let mut next = vec![0u32; len];
+            for (i, &c) in data.iter().enumerate() {
+                next[starts[c as usize]] = i as u32;
+                starts[c as usize] += 1;
+            };
+
+            Ok(walk_parallel(&next, data, &rows, interval, threads))
+        },
+        DecodeMode::Small => {
+            let mut next = Packed::new(len);
+            for (i, &c) in data.iter().enumerate() {
+                next.set(starts[c as usize], i);
+                starts[c as usize] += 1;
+            };
+
+            Ok(walk_parallel(&next, data, &rows, interval, threads))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use bwt::inverse::Packed;
+    use bwt::{decode_parallel, DecodeMode};
+
+    #[test]
+    fn packed() {
+        for &len in &[1, 2, 63, 64, 1000, 900_000] {
+            let mut packed = Packed::new(len);
+            let vals = (0..len.min(5000)).map(|i| (i * 7919) % len).collect::<Vec<usize>>();
+
+            for (i, &val) in vals.iter().enumerate() {
+                packed.set(i, val);
+            };
+            for (i, &val) in vals.iter().enumerate() {
+                assert_eq!(packed.get(i), val);
+            };
+        };
+
+        assert_eq!(Packed::new(900_000).width, 20);
+    }
+
+    #[test]
+    fn modes_agree() {
+        let inputs: [&[u8]; 5] = [b"a", b"banana", b"this is a test.", &[7; 300],
+                                  include_bytes!("inverse.rs")];
+
+        for &input in inputs.iter() {
+            let encoded = bwt::encode(input);
+
+            assert_eq!(bwt::decode_with(&encoded, DecodeMode::Fast).unwrap(), input);
+            assert_eq!(bwt::decode_with(&encoded, DecodeMode::Small).unwrap(), input);
+        };
+    }
+
+    #[test]
+    fn entry_points() {
+        let input = include_bytes!("inverse.rs").repeat(3);
+
+        for &entries in &[0, 1, 7, 100] {
+            let encoded = bwt::encode_with_entries(&input, entries);
+
+            for &mode in &[DecodeMode::Fast, DecodeMode::Small] {
+                for &threads in &[1, 3] {
+                    assert_eq!(decode_parallel(&encoded, mode, threads).unwrap(), input);
+                };
+            };
+        };
+
+        let short: &[u8] = b"abcabcab";
+        assert_eq!(decode_parallel(&bwt::encode_with_entries(short, 20),
+                                   DecodeMode::Fast, 4).unwrap(), short);
+    }
+}
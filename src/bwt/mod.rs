@@ -0,0 +1,340 @@
+mod bijective;
+mod doubling;
+mod fm_index;
+mod inverse;
+mod sais;
+mod schindler;
+mod sentinel;
+mod suffix;
+mod symbols;
+use std::io;
+use std::iter;
+use byteorder::{BigEndian, ByteOrder};
+
+pub use self::bijective::{decode_bijective, encode_bijective};
+pub use self::fm_index::{FmIndex, SampledSa};
+pub use self::inverse::{decode_parallel, decode_with, DecodeMode};
+pub use self::schindler::{decode_schindler, encode_schindler};
+pub use self::sentinel::{decode_sentinel, encode_sentinel};
+pub use self::sentinel::{rotation_to_sentinel, sentinel_to_rotation};
+pub use self::suffix::{inverse_suffix_array, lcp_array, suffix_array};
+pub use self::symbols::{decode_symbols, encode_symbols};
+
+// Set in the primary index when more entry points follow it.
+const ENTRIES: u32 = 1 << 31;
+
+// The index of the smallest rotation of `data`, found by racing two candidates and skipping past
+// whichever loses (the "minimum expression" algorithm).  Linear time.
+fn least_rotation<T: Ord>(data: &[T]) -> usize {
+    let n = data.len();
+    let (mut i, mut j, mut k) = (0, 1, 0);
+
+    while i < n && j < n && k < n {
+        let (a, b) = (&data[(i + k) % n], &data[(j + k) % n]);
+        if a == b {
+            k += 1;
+            continue;
+        };
+
+        if a > b {
+            i += k + 1;
+        } else {
+            j += k + 1;
+        };
+        if i == j {
+            j += 1;
+        };
+        k = 0;
+    };
+
+    i.min(j)
+}
+
+// The length of the shortest string that `data` is a repetition of.
+// It's `n` less the longest proper border of `data`, found with the KMP failure function, when
+// that divides `n`.  Linear time.
+fn period<T: PartialEq>(data: &[T]) -> usize {
+    let n = data.len();
+    if n == 0 {
+        return 0;
+    };
+
+    // The length of the longest proper border of each prefix.
+    let mut border = vec![0; n];
+    for i in 1..n {
+        let mut k = border[i - 1];
+        while k > 0 && data[i] != data[k] {
+            k = border[k - 1];
+        };
+        if data[i] == data[k] {
+            k += 1;
+        };
+        border[i] = k;
+    };
+
+    let p = n - border[n - 1];
+    if n.is_multiple_of(p) { p } else { n }
+}
+
+/// Sorts the rotations of `data` and writes the last byte of each, after the row holding `data`
+/// itself as a big-endian u32.
+///
+/// Rotations are sorted with `suffix_array`.  Rotated to start at its smallest rotation and
+/// cut down to one period, the block becomes a Lyndon word, and the suffixes of a Lyndon word
+/// sort in the same order as its rotations.  A block made of `k` repeats has each of those rows
+/// `k` times over; the index then points at the first of the rows equal to `data`, all of which
+/// decode the same.  That's the only way the output can differ from sorting the rotations
+/// directly, whose unstable sort could leave the index at any of those rows.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    encode_with_entries(data, 0)
+}
+
+/// Like `encode`, but also records the rows of `entries` more rotations, spread evenly
+/// over `data`, so the decoder can start from several places at once.
+///
+/// With entries, the primary index has its top bit set and is followed by the distance between
+/// entries, their number and their rows, all as big-endian u32s.
+pub fn encode_with_entries(data: &[u8], entries: usize) -> Vec<u8> {
+    encode_sampled(data, entries, 0).0
+}
+
+/// Like `encode_with_entries`, but also samples the suffix array every `rate` bytes of `data`
+/// for an `FmIndex` to locate matches with.  No samples are taken if `rate` is 0.
+pub fn encode_sampled(data: &[u8], entries: usize, rate: usize) -> (Vec<u8>, SampledSa) {
+    transform(data, entries, rate, suffix_array)
+}
+
+fn transform<F>(data: &[u8], entries: usize, rate: usize, sort: F) -> (Vec<u8>, SampledSa)
+    where F: FnOnce(&[u8]) -> Vec<u32> {
+    let len = data.len();
+
+    if len == 0 {
+        return (vec![0; 4], SampledSa { rate: rate.max(1), period: 0, rows: Vec::new() });
+    };
+
+    let start = least_rotation(data);
+    let mut lyndon = Vec::with_capacity(len);
+    lyndon.extend_from_slice(&data[start..]);
+    lyndon.extend_from_slice(&data[..start]);
+
+    let period = period(&lyndon);
+    let repeats = len / period;
+    lyndon.truncate(period);
+
+    let suffixes = sort(&lyndon);
+
+    // The rotations we want the rows of, starting with `data` itself, and where each starts
+    // within the Lyndon word, sorted by the latter.
+    let interval = len.div_ceil(entries + 1);
+    let mut wanted = (0..len).
+        step_by(interval).
+        map(|pos| (pos + len - start) % period).
+        enumerate().
+        map(|(slot, suffix)| (suffix, slot)).
+        collect::<Vec<(usize, usize)>>();
+    wanted.sort_unstable();
+
+    // Samples are taken by position within one period, which is the position in `data` unless
+    // `data` repeats.
+    let mut samples = SampledSa {
+        rate: rate.max(1),
+        period,
+        rows: vec![0; if rate > 0 { period.div_ceil(rate) } else { 0 }],
+    };
+
+    let mut rows = vec![0; wanted.len()];
+    let header_len = if rows.len() > 1 { 12 + 4 * (rows.len() - 1) } else { 4 };
+    let mut buf = vec![0; header_len];
+    buf.reserve(len);
+
+    for (i, &suffix) in suffixes.iter().enumerate() {
+        let suffix = suffix as usize;
+        let last = lyndon[(suffix + period - 1) % period];
+        buf.extend(iter::repeat_n(last, repeats));
+
+        let from = wanted.partition_point(|&(s, _)| s < suffix);
+        for &(_, slot) in wanted[from..].iter().take_while(|&&(s, _)| s == suffix) {
+            rows[slot] = (i * repeats) as u32;
+        };
+
+        let pos = (suffix + start) % period;
+        if rate > 0 && pos.is_multiple_of(rate) {
+            samples.rows[pos / rate] = (i * repeats) as u32;
+        };
+    };
+
+    // Write output index.
+    if rows.len() > 1 {
+        BigEndian::write_u32(&mut buf[0..4], rows[0] | ENTRIES);
+        BigEndian::write_u32(&mut buf[4..8], interval as u32);
+        BigEndian::write_u32(&mut buf[8..12], (rows.len() - 1) as u32);
+        BigEndian::write_u32_into(&rows[1..], &mut buf[12..header_len]);
+    } else {
+        BigEndian::write_u32(&mut buf[0..4], rows[0]);
+    };
+
+    (buf, samples)
+}
+
+pub fn decode(buf: &[u8]) -> io::Result<Vec<u8>> {
+    decode_with(buf, DecodeMode::Fast)
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{BigEndian, ByteOrder};
+    use std::io;
+    use std::str;
+    use bwt;
+    use bwt::period;
+    use testdata::Random;
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn encode() {
+        let input = "this is a test.".as_bytes();
+        let expected_output = "ssat tt hiies .".as_bytes();
+        let expected_idx = 14;
+
+        let val = bwt::encode(&input);
+        let idx = BigEndian::read_u32(&val[0..4]);
+
+        println!("{}    {}", 
+                 str::from_utf8(&val[4..]).unwrap(),
+                 str::from_utf8(expected_output).unwrap());
+
+        assert_eq!(expected_output, &val[4..] as &[u8]);
+        assert_eq!(expected_idx, idx);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn encode_2() {
+        let expected_output = "BNN^AA|A".as_bytes();
+        let expected_idx = 6;
+        let input = "^BANANA|".as_bytes();
+
+        let encoded = bwt::encode(&input);
+
+        let idx = BigEndian::read_u32(&encoded[0..4]);
+        let output = &encoded[4..] as &[u8];
+
+        assert_eq!(expected_output, output);
+        assert_eq!(expected_idx, idx);
+    }
+
+    #[test]
+    fn decode() {
+        let input = [0, 0, 0, 14].iter().chain("ssat tt hiies .".as_bytes().iter()).cloned().collect::<Vec<_>>();
+        let expected_string = "this is a test.".as_bytes();
+
+        let output = bwt::decode(&input).unwrap();
+
+        println!("returned str: {}", str::from_utf8(&output as &[u8]).unwrap());
+
+        assert_eq!(expected_string, &output as &[u8]);
+    }
+
+    // The original encoder, as it was before suffix arrays.
+    fn sort_rotations(data: &[u8]) -> Vec<u8> {
+        let len = data.len();
+
+        let mut data_looped = Vec::with_capacity(len + len - 1);
+        data_looped.extend(data);
+        data_looped.extend(&data[..(len-1)]);
+
+        let mut suffixes = (0..len).collect::<Vec<usize>>();
+        suffixes.sort_unstable_by_key(|&s| &data_looped[s..(s + len)]);
+
+        let mut buf = vec![0; 4];
+        for (i, &suffix) in suffixes.iter().enumerate() {
+            buf.push(data_looped[suffix + len - 1]);
+
+            if suffix == 0 {
+                BigEndian::write_u32(&mut buf[0..4], i as u32);
+            };
+        };
+
+        buf
+    }
+
+    #[test]
+    fn matches_rotation_sort() {
+        let mut random = Random::new(7);
+
+        let mut inputs: Vec<Vec<u8>> = vec![
+            b"a".to_vec(), b"ba".to_vec(), b"banana".to_vec(), b"nanaba".to_vec(),
+            vec![b'x'; 64], b"ab".repeat(20), b"bab".repeat(15), b"abaab".repeat(9),
+            include_bytes!("mod.rs").to_vec(),
+        ];
+        for &alphabet in &[2, 3, 256] {
+            inputs.push(random.bytes(3000, alphabet));
+        };
+
+        for input in &inputs {
+            let encoded = bwt::encode(input);
+            let original = sort_rotations(input);
+
+            // A block that repeats has several rows equal to it, and the original encoder's
+            // unstable sort could point at any of them.  They all decode the same.
+            if period(input) == input.len() {
+                assert_eq!(encoded, original);
+            } else {
+                assert_eq!(&encoded[4..], &original[4..]);
+                assert_eq!(&bwt::decode(&original).unwrap(), input);
+            };
+            let entries = bwt::encode_with_entries(input, 5);
+            assert_eq!(&entries[entries.len() - input.len()..], &encoded[4..]);
+            assert_eq!(&bwt::decode(&entries).unwrap(), input);
+            assert_eq!(&bwt::decode(&encoded).unwrap(), input);
+        };
+    }
+
+    #[test]
+    fn periods() {
+        let inputs: [(&[u8], usize); 8] = [(b"", 0), (b"a", 1), (b"aaaa", 1), (b"abab", 2),
+                                           (b"abaab", 5), (b"abaaba", 3), (b"aabaab", 3),
+                                           (b"abcabcab", 8)];
+
+        for &(input, expected) in inputs.iter() {
+            assert_eq!(period(input), expected, "{:?}", input);
+        };
+    }
+
+    #[test]
+    fn repetitive_block() {
+        let input = vec![b'z'; 1 << 20];
+        let encoded = bwt::encode(&input);
+
+        assert_eq!(BigEndian::read_u32(&encoded[0..4]), 0);
+        assert_eq!(&encoded[4..], &input[..]);
+    }
+
+    #[test]
+    fn tiny_blocks() {
+        let text = b"abracadabra ab";
+
+        for len in 0..17 {
+            for input in &[vec![b'q'; len], text.iter().cycle().take(len).cloned().collect()] {
+                let encoded = bwt::encode(input);
+                assert_eq!(encoded.len(), input.len() + 4);
+                assert_eq!(&bwt::decode(&encoded).unwrap(), input);
+                assert_eq!(&bwt::decode(&bwt::encode_with_entries(input, 3)).unwrap(), input);
+                assert_eq!(&bwt::decode_bijective(&bwt::encode_bijective(input)), input);
+                assert_eq!(&bwt::decode_sentinel(&bwt::encode_sentinel(input)).unwrap(), input);
+            };
+        };
+
+        // Too short for a header or for the entry points it promises, an index past the end, and
+        // entry points 0 bytes apart.
+        let bad: [&[u8]; 8] = [b"", b"\0", b"\0\0\0", b"\0\0\0\x09a", b"\0\0\0\x01",
+                               b"\x80\0\0\0\0\0\0\x01",
+                               b"\x80\0\0\0\0\0\0\x01\0\0\0\x01",
+                               b"\x80\0\0\0\0\0\0\0\0\0\0\x01\0\0\0\0ab"];
+        for &buf in bad.iter() {
+            let err = bwt::decode(buf).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        };
+        assert_eq!(bwt::decode(&[0, 0, 0, 0]).unwrap(), b"");
+    }
+}
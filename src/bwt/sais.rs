@@ -0,0 +1,238 @@
+// Suffix array construction by induced sorting (SA-IS, Nong, Zhang and Chan 2009), which runs in
+// linear time whatever the input looks like.  The end of the text acts as a sentinel smaller than
+// every symbol, so a suffix sorts before any longer suffix it is a prefix of.
+//
+// Besides the text and the `u32` suffix array, the only memory needed is one bit per symbol for
+// the suffix types and the bucket counts; the reduced problem for the recursion is kept inside
+// the suffix array itself.
+
+const EMPTY: u32 = u32::MAX;
+
+// Whether each suffix is S-type (smaller than the suffix after it) or L-type, one bit each.
+struct Types {
+    bits: Vec<u64>,
+}
+
+impl Types {
+    fn new<T: Copy + Into<u64>>(text: &[T]) -> Types {
+        let n = text.len();
+        let mut types = Types { bits: vec![0; n.div_ceil(64)] };
+
+        // The last suffix is L-type, being bigger than the empty one after it.
+        let mut s = false;
+        for i in (0..n.saturating_sub(1)).rev() {
+            let (a, b) = (text[i].into(), text[i + 1].into());
+            s = a < b || (a == b && s);
+            if s {
+                types.bits[i >> 6] |= 1 << (i & 63);
+            };
+        };
+
+        types
+    }
+
+    fn is_s(&self, i: usize) -> bool {
+        self.bits[i >> 6] & (1 << (i & 63)) != 0
+    }
+
+    // Leftmost S-type: an S-type suffix right after an L-type one.
+    fn is_lms(&self, i: usize) -> bool {
+        i > 0 && self.is_s(i) && !self.is_s(i - 1)
+    }
+}
+
+fn bucket_heads(counts: &[u32], heads: &mut [u32]) {
+    let mut sum = 0;
+    for (head, &count) in heads.iter_mut().zip(counts) {
+        *head = sum;
+        sum += count;
+    };
+}
+
+fn bucket_tails(counts: &[u32], tails: &mut [u32]) {
+    let mut sum = 0;
+    for (tail, &count) in tails.iter_mut().zip(counts) {
+        sum += count;
+        *tail = sum;
+    };
+}
+
+fn induce<T: Copy + Into<u64>>(text: &[T], sa: &mut [u32], types: &Types, counts: &[u32],
+                               buckets: &mut [u32]) {
+    let n = text.len();
+    let sym = |i: usize| text[i].into() as usize;
+
+    // L-type suffixes, left to right from the bucket heads.  The suffix just before the sentinel
+    // comes first.
+    bucket_heads(counts, buckets);
+    sa[buckets[sym(n - 1)] as usize] = (n - 1) as u32;
+    buckets[sym(n - 1)] += 1;
+
+    for i in 0..n {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && !types.is_s(j as usize - 1) {
+            let c = sym(j as usize - 1);
+            sa[buckets[c] as usize] = j - 1;
+            buckets[c] += 1;
+        };
+    };
+
+    // S-type suffixes, right to left from the bucket tails.
+    bucket_tails(counts, buckets);
+    for i in (0..n).rev() {
+        let j = sa[i];
+        if j != EMPTY && j > 0 && types.is_s(j as usize - 1) {
+            let c = sym(j as usize - 1);
+            buckets[c] -= 1;
+            sa[buckets[c] as usize] = j - 1;
+        };
+    };
+}
+
+// Whether the LMS substrings (from one LMS position to the next, inclusive) at `a` and `b` differ.
+fn lms_substrings_differ<T: Copy + Into<u64>>(text: &[T], types: &Types, a: usize,
+                                              b: usize) -> bool {
+    let n = text.len();
+
+    for d in 0.. {
+        // Only one substring runs into the sentinel, and it's unlike any other.
+        if a + d == n || b + d == n {
+            return true;
+        };
+        if text[a + d].into() != text[b + d].into() || types.is_s(a + d) != types.is_s(b + d) {
+            return true;
+        };
+        if d > 0 && (types.is_lms(a + d) || types.is_lms(b + d)) {
+            return false;
+        };
+    };
+
+    unreachable!()
+}
+
+/// Sorts the suffixes of `text`, whose symbols are all below `alphabet`.  `sa` must be as long
+/// as `text`.
+pub fn sais<T: Copy + Into<u64>>(text: &[T], sa: &mut [u32], alphabet: usize) {
+    let n = text.len();
+    assert!(sa.len() == n && n < EMPTY as usize);
+
+    if n <= 1 {
+        if n == 1 {
+            sa[0] = 0;
+        };
+        return;
+    };
+
+    let types = Types::new(text);
+    let mut counts = vec![0; alphabet];
+    for &c in text {
+        counts[c.into() as usize] += 1;
+    };
+    let mut buckets = vec![0; alphabet];
+
+    // Sort the LMS substrings: drop the LMS positions at their bucket tails in any order and let
+    // induced sorting put them in order.
+    sa.iter_mut().for_each(|x| *x = EMPTY);
+    bucket_tails(&counts, &mut buckets);
+    for i in (1..n).filter(|&i| types.is_lms(i)) {
+        let c = text[i].into() as usize;
+        buckets[c] -= 1;
+        sa[buckets[c] as usize] = i as u32;
+    };
+    induce(text, sa, &types, &counts, &mut buckets);
+
+    // Gather the sorted LMS positions at the front.
+    let mut m = 0;
+    for i in 0..n {
+        if types.is_lms(sa[i] as usize) {
+            sa[m] = sa[i];
+            m += 1;
+        };
+    };
+
+    // Name each LMS substring by its rank, with equal substrings sharing a name.  LMS positions
+    // are at least two apart, so `m + pos / 2` gives each one a slot of its own.
+    sa[m..].iter_mut().for_each(|x| *x = EMPTY);
+    let mut names = 0;
+    let mut prev = None;
+    for i in 0..m {
+        let pos = sa[i] as usize;
+        if prev.is_none_or(|prev| lms_substrings_differ(text, &types, pos, prev)) {
+            names += 1;
+            prev = Some(pos);
+        };
+        sa[m + pos / 2] = names - 1;
+    };
+
+    // Pack the names into the back, giving the reduced string in text order.
+    let mut j = n;
+    for i in (m..n).rev() {
+        if sa[i] != EMPTY {
+            j -= 1;
+            sa[j] = sa[i];
+        };
+    };
+
+    // Sort the reduced string, recursing only if some names are shared.
+    {
+        let (head, reduced) = sa.split_at_mut(n - m);
+        if (names as usize) < m {
+            sais(&*reduced, &mut head[..m], names as usize);
+        } else {
+            for (i, &name) in reduced.iter().enumerate() {
+                head[name as usize] = i as u32;
+            };
+        };
+    }
+
+    // Turn ranks in the reduced string back into text positions.
+    for (j, i) in (n - m..).zip((1..n).filter(|&i| types.is_lms(i))) {
+        sa[j] = i as u32;
+    };
+    for i in 0..m {
+        sa[i] = sa[n - m + sa[i] as usize];
+    };
+
+    // Now that the LMS suffixes are in order, a final induced sort places the rest.
+    sa[m..].iter_mut().for_each(|x| *x = EMPTY);
+    bucket_tails(&counts, &mut buckets);
+    for i in (0..m).rev() {
+        let pos = sa[i];
+        sa[i] = EMPTY;
+        let c = text[pos as usize].into() as usize;
+        buckets[c] -= 1;
+        sa[buckets[c] as usize] = pos;
+    };
+    induce(text, sa, &types, &counts, &mut buckets);
+}
+
+#[cfg(test)]
+mod test {
+    use bwt::sais::sais;
+    use testdata::Random;
+
+    fn naive(text: &[u8]) -> Vec<u32> {
+        let mut sa = (0..text.len() as u32).collect::<Vec<u32>>();
+        sa.sort_by_key(|&i| &text[i as usize..]);
+        sa
+    }
+
+    #[test]
+    fn matches_naive_sort() {
+        let mut random = Random::new(1);
+
+        let mut inputs: Vec<Vec<u8>> = vec![
+            b"a".to_vec(), b"banana".to_vec(), b"mississippi".to_vec(), b"abracadabra".to_vec(),
+            vec![0; 100], b"ab".repeat(50), b"aab".repeat(40), include_bytes!("sais.rs").to_vec(),
+        ];
+        for &alphabet in &[2, 4, 256] {
+            inputs.push(random.bytes(2000, alphabet));
+        };
+
+        for text in &inputs {
+            let mut sa = vec![0; text.len()];
+            sais(&text[..], &mut sa, 256);
+            assert_eq!(sa, naive(text));
+        };
+    }
+}
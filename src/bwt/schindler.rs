@@ -0,0 +1,194 @@
+// The Schindler transform, or ST-k: like the BWT, but rotations are sorted on their first `k`
+// bytes only, with ties left in order of position.  That takes `k` passes of a counting sort,
+// so it's linear in the block for a fixed `k`, and for small `k` much faster than a full sort.
+// It compresses a little worse, as rows that only share short contexts end up next to each
+// other.
+//
+// The output is `k` as a byte, the row holding the block itself as a big-endian u32, then the
+// last byte of each row.
+//
+// Undoing it needs to know where each order-`k` context starts.  The rows are sorted by context,
+// so pairing each row's last byte with the order-`j` context of the row gives, once sorted, the
+// order-`j + 1` context of each row, and `k` rounds of that give the order-`k` ones.  Walking
+// back from the block's own row then visits each context's rotations from the last position to
+// the first, which is the order of their rows from the bottom up.
+use std::io;
+use byteorder::{BigEndian, ByteOrder};
+
+// Stably sorts `rows` by `key`, which is below `range` for every row.
+fn counting_sort<F>(rows: &[u32], range: usize, key: F) -> Vec<u32>
+    where F: Fn(u32) -> usize {
+    let mut starts = vec![0; range + 1];
+    for &row in rows {
+        starts[key(row) + 1] += 1;
+    };
+    for i in 1..range {
+        starts[i + 1] += starts[i];
+    };
+
+    let mut sorted = vec![0; rows.len()];
+    for &row in rows {
+        let key = key(row);
+        sorted[starts[key]] = row;
+        starts[key] += 1;
+    };
+    sorted
+}
+
+/// The ST-`k` transform of `data`.
+pub fn encode_schindler(data: &[u8], k: u8) -> Vec<u8> {
+    let len = data.len();
+    let mut buf = vec![k, 0, 0, 0, 0];
+    buf.reserve(len);
+
+    // Sort on the last byte of the context first, then each one before it.
+    let mut rotations = (0..len as u32).collect::<Vec<u32>>();
+    for offset in (0..k as usize).rev() {
+        let byte = |pos: u32| data[(pos as usize + offset) % len] as usize;
+        rotations = counting_sort(&rotations, 256, byte);
+    };
+
+    for (row, &pos) in rotations.iter().enumerate() {
+        if pos == 0 {
+            BigEndian::write_u32(&mut buf[1..5], row as u32);
+        };
+        buf.push(data[(pos as usize + len - 1) % len]);
+    };
+
+    buf
+}
+
+/// Undoes `encode_schindler`.
+pub fn decode_schindler(buf: &[u8]) -> io::Result<Vec<u8>> {
+    if buf.len() < 5 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "ST block is too short"));
+    };
+    let k = buf[0] as usize;
+    let primary = BigEndian::read_u32(&buf[1..5]) as usize;
+    let last = &buf[5..];
+    let len = last.len();
+
+    // An empty block still has row 0 as its primary.
+    if primary >= len.max(1) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "ST index is past the end of the block"));
+    };
+
+    // The context of each row, as a number that sorts like it, and the context of the row one
+    // rotation before it, one order up.
+    let mut contexts = vec![0u32; len];
+    let mut previous = vec![0u32; len];
+
+    // The rows are already in order of context, so sorting them on their last bytes alone sorts
+    // them on both.
+    let rows = (0..len as u32).collect::<Vec<u32>>();
+    let rows = counting_sort(&rows, 256, |row| last[row as usize] as usize);
+
+    for _ in 0..k {
+        let mut longer = vec![0u32; len];
+        let mut key = None;
+        let mut count = 0;
+        for (i, &row) in rows.iter().enumerate() {
+            let row_key = (last[row as usize], contexts[row as usize]);
+            if key != Some(row_key) {
+                key = Some(row_key);
+                count += 1;
+            };
+            longer[i] = count as u32 - 1;
+            previous[row as usize] = count as u32 - 1;
+        };
+        contexts = longer;
+    };
+
+    // Where each context's rows end.
+    let mut ends = vec![0; contexts.last().map_or(0, |&last| last as usize + 1)];
+    for (row, &context) in contexts.iter().enumerate() {
+        ends[context as usize] = row + 1;
+    };
+
+    // A corrupt block can run a context out of rows, or into the next context's.
+    let mut out = vec![0; len];
+    let mut row = primary;
+    for byte in out.iter_mut().rev() {
+        *byte = last[row];
+
+        let context = previous[row] as usize;
+        row = match ends[context].checked_sub(1) {
+            Some(end) if contexts[end] as usize == context => end,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                           "ST block visits a context too often")),
+        };
+        ends[context] = row;
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use std::io;
+    use byteorder::{BigEndian, ByteOrder};
+
+    // Sorts rotations by their first `k` bytes, then by position.
+    fn naive(data: &[u8], k: u8) -> Vec<u8> {
+        let len = data.len();
+        let context = |pos: usize| (0..k as usize).
+            map(|i| data[(pos + i) % len]).
+            collect::<Vec<u8>>();
+
+        let mut rotations = (0..len).collect::<Vec<usize>>();
+        rotations.sort_by_key(|&pos| (context(pos), pos));
+
+        let mut buf = vec![k, 0, 0, 0, 0];
+        let primary = rotations.iter().position(|&pos| pos == 0).unwrap_or(0);
+        BigEndian::write_u32(&mut buf[1..5], primary as u32);
+        buf.extend(rotations.iter().map(|&pos| data[(pos + len - 1) % len]));
+        buf
+    }
+
+    #[test]
+    fn matches_naive_sort() {
+        let inputs: [&[u8]; 6] = [b"", b"a", b"banana", b"abababab", b"mississippi river",
+                                  &include_bytes!("schindler.rs")[..2000]];
+
+        for &input in inputs.iter() {
+            for &k in &[0, 1, 2, 3, 6, 20] {
+                let encoded = bwt::encode_schindler(input, k);
+
+                assert_eq!(encoded, naive(input, k));
+                assert_eq!(bwt::decode_schindler(&encoded).unwrap(), input);
+            };
+        };
+    }
+
+    #[test]
+    fn full_order_is_the_bwt() {
+        let input = b"this is a test.";
+        let encoded = bwt::encode_schindler(input, input.len() as u8);
+
+        assert_eq!(&encoded[1..], &bwt::encode(input)[..]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = include_bytes!("schindler.rs");
+
+        for &k in &[1, 4, 8] {
+            assert_eq!(&bwt::decode_schindler(&bwt::encode_schindler(input, k)).unwrap()[..],
+                       &input[..]);
+        };
+    }
+
+    #[test]
+    fn bad_blocks() {
+        // Too short, an index past the end, and a row whose context comes round twice.
+        let bad: [&[u8]; 5] = [b"", &[2, 0, 0, 0], &[2, 0, 0, 0, 1], &[2, 0, 0, 0, 6, b'a'],
+                               &[1, 0, 0, 0, 0, b'a', b'b']];
+
+        for &buf in bad.iter() {
+            let err = bwt::decode_schindler(buf).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        };
+    }
+}
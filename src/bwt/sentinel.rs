@@ -0,0 +1,111 @@
+// The BWT of the block with an end marker, `$`, smaller than every byte added to its end, which
+// is the form most string indexing tools use.  Sorting the rotations of `data$` is sorting the
+// suffixes of `data`, so it comes straight from the suffix array.
+//
+// `$` can't be told apart from a byte in the output, so it's left out and its row is written in
+// front instead, as a big-endian u32 in the place of the primary index.  The output has one row
+// more than the block: the first is always `$data`.
+use bwt::inverse::first_rows;
+use bwt::{decode, encode, suffix_array};
+use std::io;
+use byteorder::{BigEndian, ByteOrder};
+
+/// The BWT of `data$`, with `$` left out and its row written in front.
+pub fn encode_sentinel(data: &[u8]) -> Vec<u8> {
+    let mut buf = vec![0; 4];
+    buf.reserve(data.len());
+
+    // Row 0 is the empty suffix, preceded by the last byte.
+    if let Some(&last) = data.last() {
+        buf.push(last);
+    };
+
+    for (row, suffix) in suffix_array(data).into_iter().enumerate() {
+        match suffix {
+            0 => BigEndian::write_u32(&mut buf[0..4], row as u32 + 1),
+            _ => buf.push(data[suffix as usize - 1]),
+        };
+    };
+
+    buf
+}
+
+/// Undoes `encode_sentinel`.
+pub fn decode_sentinel(buf: &[u8]) -> io::Result<Vec<u8>> {
+    if buf.len() < 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "Sentinel BWT block is too short for its index"));
+    };
+    let sentinel = BigEndian::read_u32(&buf[0..4]) as usize;
+    let data = &buf[4..];
+    let len = data.len();
+
+    // Only the empty block has `$` in row 0.
+    if sentinel > len || (sentinel == 0 && len > 0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  "Sentinel BWT index is outside the block"));
+    };
+
+    // `$` has the first row to itself; every byte's rows come after it.
+    let mut starts = first_rows(data);
+    starts.iter_mut().for_each(|start| *start += 1);
+
+    let mut next = vec![0u32; len + 1];
+    next[0] = sentinel as u32;
+    for (i, &c) in data.iter().enumerate() {
+        let row = if i < sentinel { i } else { i + 1 };
+        next[starts[c as usize]] = row as u32;
+        starts[c as usize] += 1;
+    };
+
+    // Step past `$data` to `data$`, then spell out the block.
+    let mut row = next[0] as usize;
+    Ok((0..len).map(|_| {
+        row = next[row] as usize;
+        data[if row < sentinel { row } else { row - 1 }]
+    }).collect())
+}
+
+/// Turns the output of `bwt::encode` into the output of `encode_sentinel` for the same block.
+pub fn rotation_to_sentinel(buf: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(encode_sentinel(&decode(buf)?))
+}
+
+/// Turns the output of `encode_sentinel` into the output of `bwt::encode` for the same block.
+pub fn sentinel_to_rotation(buf: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(encode(&decode_sentinel(buf)?))
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+
+    #[test]
+    fn banana() {
+        // "banana$" sorts as $banana, a$banan, ana$ban, anana$b, banana$, na$bana, nana$ba.
+        let encoded = bwt::encode_sentinel(b"banana");
+
+        assert_eq!(encoded, [&[0, 0, 0, 4][..], b"annbaa"].concat());
+        assert_eq!(bwt::decode_sentinel(&encoded).unwrap(), b"banana");
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 6] = [b"", b"a", b"$$$", b"abab", &[0; 50],
+                                  include_bytes!("sentinel.rs")];
+
+        for &input in inputs.iter() {
+            let sentinel = bwt::encode_sentinel(input);
+            let rotation = bwt::encode(input);
+
+            assert_eq!(sentinel.len(), input.len() + 4);
+            assert_eq!(bwt::decode_sentinel(&sentinel).unwrap(), input);
+            assert_eq!(bwt::rotation_to_sentinel(&rotation).unwrap(), sentinel);
+            assert_eq!(bwt::sentinel_to_rotation(&sentinel).unwrap(), rotation);
+        };
+
+        for &buf in &[&b"\0\0"[..], b"\0\0\0\0a", b"\0\0\0\x02a"] {
+            assert!(bwt::decode_sentinel(buf).is_err());
+        };
+    }
+}
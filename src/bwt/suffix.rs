@@ -0,0 +1,76 @@
+// Suffix arrays and the tables built on them, for indexing as well as the BWT.  Suffixes are
+// ordered with the end of the data smaller than any byte, so a suffix sorts before the longer
+// suffixes it is a prefix of.
+use bwt::sais::sais;
+
+/// The starting positions of the suffixes of `data`, in sorted order.  Linear time.
+pub fn suffix_array(data: &[u8]) -> Vec<u32> {
+    let mut sa = vec![0; data.len()];
+    sais(data, &mut sa, 256);
+    sa
+}
+
+/// The rank of each suffix: `isa[sa[i]] == i`.
+pub fn inverse_suffix_array(sa: &[u32]) -> Vec<u32> {
+    let mut isa = vec![0; sa.len()];
+    for (i, &s) in sa.iter().enumerate() {
+        isa[s as usize] = i as u32;
+    };
+    isa
+}
+
+/// The length of the longest common prefix of each suffix in `sa` and the one before it, with 0
+/// for the first.  Kasai's algorithm, in linear time.
+pub fn lcp_array(data: &[u8], sa: &[u32], isa: &[u32]) -> Vec<u32> {
+    let n = data.len();
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+
+    // Going through the suffixes in text order, each common prefix is at most one shorter than
+    // the last.
+    for i in 0..n {
+        let rank = isa[i] as usize;
+        if rank == 0 {
+            h = 0;
+            continue;
+        };
+
+        let j = sa[rank - 1] as usize;
+        while i + h < n && j + h < n && data[i + h] == data[j + h] {
+            h += 1;
+        };
+        lcp[rank] = h as u32;
+        h = h.saturating_sub(1);
+    };
+
+    lcp
+}
+
+#[cfg(test)]
+mod test {
+    use bwt::{inverse_suffix_array, lcp_array, suffix_array};
+
+    #[test]
+    fn banana() {
+        let data = b"banana";
+        let sa = suffix_array(data);
+        let isa = inverse_suffix_array(&sa);
+
+        assert_eq!(sa, vec![5, 3, 1, 0, 4, 2]);
+        assert_eq!(isa, vec![3, 2, 5, 1, 4, 0]);
+        assert_eq!(lcp_array(data, &sa, &isa), vec![0, 1, 3, 0, 0, 2]);
+    }
+
+    #[test]
+    fn lcp_matches_naive() {
+        let data = include_bytes!("suffix.rs");
+        let sa = suffix_array(data);
+        let lcp = lcp_array(data, &sa, &inverse_suffix_array(&sa));
+
+        for i in 1..sa.len() {
+            let (a, b) = (&data[sa[i - 1] as usize..], &data[sa[i] as usize..]);
+            assert!(a < b);
+            assert_eq!(lcp[i] as usize, a.iter().zip(b).take_while(|(x, y)| x == y).count());
+        };
+    }
+}
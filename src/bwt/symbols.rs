@@ -0,0 +1,94 @@
+// The BWT over symbols wider than a byte, such as the characters of a text, for when splitting
+// them into bytes would spread one character's contexts over several.  Sorted and undone the
+// same way as `bwt::encode`, but with the primary index returned rather than written in front.
+use bwt::sais::sais;
+use bwt::{least_rotation, period};
+use std::iter;
+
+/// The last symbol of each sorted rotation of `data`, whose symbols are all below `alphabet`,
+/// and the row holding `data` itself.
+pub fn encode_symbols(data: &[u32], alphabet: usize) -> (usize, Vec<u32>) {
+    let len = data.len();
+    if len == 0 {
+        return (0, Vec::new());
+    };
+
+    let start = least_rotation(data);
+    let mut lyndon = Vec::with_capacity(len);
+    lyndon.extend_from_slice(&data[start..]);
+    lyndon.extend_from_slice(&data[..start]);
+
+    let period = period(&lyndon);
+    let repeats = len / period;
+    lyndon.truncate(period);
+
+    let mut suffixes = vec![0; period];
+    sais(&lyndon, &mut suffixes, alphabet);
+
+    let mut last = Vec::with_capacity(len);
+    let mut primary = 0;
+    for (i, &suffix) in suffixes.iter().enumerate() {
+        let suffix = suffix as usize;
+        if suffix == (len - start) % period {
+            primary = i * repeats;
+        };
+        last.extend(iter::repeat_n(lyndon[(suffix + period - 1) % period], repeats));
+    };
+
+    (primary, last)
+}
+
+/// Undoes `encode_symbols`.
+pub fn decode_symbols(primary: usize, last: &[u32], alphabet: usize) -> Vec<u32> {
+    let mut starts = vec![0; alphabet + 1];
+    for &c in last {
+        starts[c as usize + 1] += 1;
+    };
+    for c in 1..alphabet {
+        starts[c + 1] += starts[c];
+    };
+
+    let mut next = vec![0u32; last.len()];
+    for (i, &c) in last.iter().enumerate() {
+        next[starts[c as usize]] = i as u32;
+        starts[c as usize] += 1;
+    };
+
+    let mut row = primary;
+    last.iter().map(|_| {
+        row = next[row] as usize;
+        last[row]
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use byteorder::{BigEndian, ByteOrder};
+
+    #[test]
+    fn matches_byte_bwt() {
+        let inputs: [&[u8]; 6] = [b"", b"a", b"banana", b"abcabcabc", &[9; 40],
+                                  include_bytes!("symbols.rs")];
+
+        for &input in inputs.iter() {
+            let symbols = input.iter().map(|&b| b as u32).collect::<Vec<u32>>();
+            let (primary, last) = bwt::encode_symbols(&symbols, 256);
+            let encoded = bwt::encode(input);
+
+            assert_eq!(primary, BigEndian::read_u32(&encoded[0..4]) as usize);
+            assert_eq!(last.iter().map(|&c| c as u8).collect::<Vec<u8>>(), &encoded[4..]);
+            assert_eq!(bwt::decode_symbols(primary, &last, 256), symbols);
+        };
+    }
+
+    #[test]
+    fn wide_symbols() {
+        let text = "ça, c'est déjà ça — ça ça ça!".chars().
+            map(|c| c as u32).
+            collect::<Vec<u32>>();
+        let (primary, last) = bwt::encode_symbols(&text, 0x2100);
+
+        assert_eq!(bwt::decode_symbols(primary, &last, 0x2100), text);
+    }
+}
@@ -0,0 +1,231 @@
+// A context-modeled binary arithmetic coder for the output of `rle::encode(&mtf::encode(..))`, in
+// the spirit of bsc's QLFC.  After the BWT and MTF, a rank is strongly predicted by the ranks
+// just before it and by where we are in a run, which an order-0 coder can't see.
+//
+// Each rank is split into binary decisions: is it 0, is it 1, then its bit length in unary and its
+// remaining bits.  Every decision has its own adaptive probability, selected by the last two
+// ranks and the length of the current run.  The run-length bytes that `rle::encode` writes after
+// four equal ranks are recognized and coded with a model of their own.
+use range::{RangeDecoder, RangeEncoder, BIT_SCALE};
+use byteorder::{BigEndian, ByteOrder};
+
+// A probability of 0 that adapts at two speeds.  The fast half tracks local changes, the slow half
+// keeps a steadier estimate; coding with their average beats either on its own.
+#[derive(Clone, Copy)]
+struct Bit {
+    fast: u16,
+    slow: u16,
+}
+
+impl Bit {
+    fn new() -> Bit {
+        Bit { fast: 1 << 15, slow: 1 << 15 }
+    }
+
+    fn p0(&self) -> u32 {
+        ((self.fast as u32 + self.slow as u32) >> 5).clamp(1, BIT_SCALE - 1)
+    }
+
+    fn update(&mut self, bit: u8) {
+        if bit == 0 {
+            self.fast += (0xffff - self.fast) >> 4;
+            self.slow += (0xffff - self.slow) >> 7;
+        } else {
+            self.fast -= self.fast >> 4;
+            self.slow -= self.slow >> 7;
+        };
+    }
+}
+
+// Lets the model be written once for both directions.  Encoders code `bit`; decoders ignore it.
+// Either way the bit actually coded is returned.
+trait BitCoder {
+    fn code(&mut self, prob: &mut Bit, bit: u8) -> u8;
+}
+
+impl BitCoder for RangeEncoder {
+    fn code(&mut self, prob: &mut Bit, bit: u8) -> u8 {
+        self.encode_bit(prob.p0(), bit);
+        prob.update(bit);
+        bit
+    }
+}
+
+impl<'a> BitCoder for RangeDecoder<'a> {
+    fn code(&mut self, prob: &mut Bit, _: u8) -> u8 {
+        let bit = self.decode_bit(prob.p0());
+        prob.update(bit);
+        bit
+    }
+}
+
+// Last rank (0, 1, 2, 3+) x rank before it (0, 1+) x run length so far (0 to 3).
+const CONTEXTS: usize = 4 * 2 * 4;
+
+struct Model {
+    zero: [Bit; CONTEXTS],
+    one: [Bit; CONTEXTS],
+    exponent: [[Bit; 6]; CONTEXTS],
+    mantissa: [[Bit; 128]; 8],
+    run_length: [[Bit; 256]; 2],
+
+    last: u8,
+    before_last: u8,
+    // Equal ranks in a row, mirroring how `rle::decode` reads them; 4 means a length comes next.
+    run: usize,
+}
+
+impl Model {
+    fn new() -> Model {
+        Model {
+            zero: [Bit::new(); CONTEXTS],
+            one: [Bit::new(); CONTEXTS],
+            exponent: [[Bit::new(); 6]; CONTEXTS],
+            mantissa: [[Bit::new(); 128]; 8],
+            run_length: [[Bit::new(); 256]; 2],
+            last: 0,
+            before_last: 0,
+            run: 0,
+        }
+    }
+
+    fn context(&self) -> usize {
+        let last = self.last.min(3) as usize;
+        let before_last = self.before_last.min(1) as usize;
+
+        (last * 2 + before_last) * 4 + self.run
+    }
+
+    fn code_rank<C: BitCoder>(&mut self, coder: &mut C, rank: u8) -> u8 {
+        let ctx = self.context();
+
+        if coder.code(&mut self.zero[ctx], (rank != 0) as u8) == 0 {
+            return 0;
+        };
+        if coder.code(&mut self.one[ctx], (rank != 1) as u8) == 0 {
+            return 1;
+        };
+
+        // From here the rank is 2 or more, so it has a bit length of 2 to 8.
+        let exponent = if rank >= 2 { 7 - rank.leading_zeros() as usize } else { 0 };
+        let mut e = 1;
+        while e < 7 && coder.code(&mut self.exponent[ctx][e - 1], (exponent > e) as u8) == 1 {
+            e += 1;
+        };
+
+        let mut node = 1;
+        for i in (0..e).rev() {
+            let bit = coder.code(&mut self.mantissa[e][node], (rank >> i) & 1);
+            node = (node << 1) | bit as usize;
+        };
+
+        node as u8
+    }
+
+    fn code_run_length<C: BitCoder>(&mut self, coder: &mut C, len: u8) -> u8 {
+        let probs = &mut self.run_length[(self.last != 0) as usize];
+        let mut node = 1;
+
+        for i in (0..8).rev() {
+            let bit = coder.code(&mut probs[node], (len >> i) & 1);
+            node = (node << 1) | bit as usize;
+        };
+
+        node as u8
+    }
+
+    fn code<C: BitCoder>(&mut self, coder: &mut C, byte: u8) -> u8 {
+        if self.run == 4 {
+            let len = self.code_run_length(coder, byte);
+            self.run = 0;
+            return len;
+        };
+
+        let rank = self.code_rank(coder, byte);
+
+        if self.run > 0 && rank == self.last {
+            self.run += 1;
+        } else {
+            self.run = 1;
+            self.before_last = self.last;
+            self.last = rank;
+        };
+
+        rank
+    }
+}
+
+/// Codes `data`, which should look like the output of `rle::encode`.  Any bytes will round-trip;
+/// they just won't compress as well.  The output starts with the length as a big-endian u32.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut model = Box::new(Model::new());
+    let mut encoder = RangeEncoder::new();
+
+    for &c in data {
+        model.code(&mut encoder, c);
+    };
+
+    let mut buf = vec![0; 4];
+    BigEndian::write_u32(&mut buf, data.len() as u32);
+    buf.extend(encoder.finish());
+
+    buf
+}
+
+pub fn decode(buf: &[u8]) -> Result<Vec<u8>, String> {
+    if buf.len() < 4 {
+        return Err(String::from("Context coded block is too short"));
+    };
+
+    // As in `range::decode`, the length isn't trusted until the bytes are there.
+    let len = BigEndian::read_u32(&buf[0..4]) as usize;
+    let mut model = Box::new(Model::new());
+    let mut decoder = RangeDecoder::new(&buf[4..]);
+    let mut out = Vec::with_capacity(len.min(buf.len() * 8));
+
+    for _ in 0..len {
+        out.push(model.code(&mut decoder, 0));
+
+        if decoder.overrun() {
+            return Err(String::from("Context coded block is truncated"));
+        };
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use cm;
+    use mtf;
+    use range;
+    use rle;
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 4] = [b"", b"a", b"abracadabra", b"this is a test."];
+
+        for &input in inputs.iter() {
+            assert_eq!(cm::decode(&cm::encode(input)).unwrap(), input);
+        };
+
+        let all = (0..=255).chain((0..=255).rev()).cycle().take(5000).collect::<Vec<u8>>();
+        assert_eq!(cm::decode(&cm::encode(&all)).unwrap(), all);
+
+        let rled = rle::encode(&[0; 1000]);
+        assert_eq!(cm::decode(&cm::encode(&rled)).unwrap(), rled);
+
+        assert!(cm::decode(&[255, 255, 255, 255, 0]).is_err());
+    }
+
+    #[test]
+    fn beats_order_0() {
+        let data = include_bytes!("cm.rs");
+        let rled = rle::encode(&mtf::encode(&bwt::encode(data)));
+        let encoded = cm::encode(&rled);
+
+        assert!(encoded.len() < range::encode(&rled).len());
+        assert_eq!(cm::decode(&encoded).unwrap(), rled);
+    }
+}
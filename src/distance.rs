@@ -0,0 +1,287 @@
+// Distance coding and inversion frequencies, two alternatives to `mtf::encode` after the BWT.
+// Both describe where each byte occurs rather than how recently it was seen, as numbers too big
+// for a byte, which `range::encode_gaps` can code.
+//
+// Distance coding (Binder) gives, for each position, how far ahead the same byte comes next,
+// counting only positions whose byte isn't known yet by then; the first position of each byte
+// comes first.  Runs cost one small number per byte, and a byte that's already known is never
+// sent again.
+//
+// Inversion frequencies (Arnavut) give, for each byte value in turn, how many larger bytes lie
+// between one occurrence and the next.  How often each byte occurs comes first.  The largest
+// byte only fills the gaps left by the others, but its occurrences are still sent, as zeros, so
+// that the counts can be checked against what follows them.
+
+// Counts of the positions still free, for finding the `k`th one after a position.
+struct Free {
+    tree: Vec<u32>,
+}
+
+impl Free {
+    fn new(len: usize) -> Free {
+        let mut tree = vec![0; len + 1];
+        for i in 1..=len {
+            tree[i] += 1;
+            let parent = i + (i & i.wrapping_neg());
+            if parent <= len {
+                tree[parent] += tree[i];
+            };
+        };
+        Free { tree }
+    }
+
+    fn take(&mut self, pos: usize) {
+        let mut i = pos + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= 1;
+            i += i & i.wrapping_neg();
+        };
+    }
+
+    // How many positions before `pos` are free.
+    fn before(&self, pos: usize) -> usize {
+        let mut i = pos;
+        let mut count = 0;
+        while i > 0 {
+            count += self.tree[i] as usize;
+            i -= i & i.wrapping_neg();
+        };
+        count
+    }
+
+    // The free position with `rank` free positions before it, if there is one.
+    fn nth(&self, mut rank: usize) -> Option<usize> {
+        let len = self.tree.len() - 1;
+        let mut pos = 0;
+        let mut step = len.next_power_of_two();
+
+        while step > 0 {
+            let next = pos + step;
+            if next <= len && (self.tree[next] as usize) <= rank {
+                pos = next;
+                rank -= self.tree[next] as usize;
+            };
+            step >>= 1;
+        };
+
+        if pos < len { Some(pos) } else { None }
+    }
+}
+
+/// Distance codes `data`: the first position of each byte value plus one, or 0 if it doesn't
+/// occur, then for each position, how many unknown positions ahead its byte next occurs, or 0.
+pub fn encode_distances(data: &[u8]) -> Vec<u32> {
+    let len = data.len();
+    let mut out = vec![0; 256];
+    out.reserve(len);
+
+    let mut next = vec![0; len];
+    let mut following = [len; 256];
+    for (i, &c) in data.iter().enumerate().rev() {
+        next[i] = following[c as usize];
+        following[c as usize] = i;
+    };
+
+    let mut free = Free::new(len);
+    for (c, &first) in following.iter().enumerate() {
+        if first < len {
+            out[c] = first as u32 + 1;
+            free.take(first);
+        };
+    };
+
+    for (i, &j) in next.iter().enumerate() {
+        if j == len {
+            out.push(0);
+        } else {
+            out.push((free.before(j + 1) - free.before(i + 1)) as u32);
+            free.take(j);
+        };
+    };
+
+    out
+}
+
+pub fn decode_distances(data: &[u32]) -> Result<Vec<u8>, String> {
+    if data.len() < 256 {
+        return Err(String::from("Distance coded block is too short"));
+    };
+    let len = data.len() - 256;
+    let mut out = vec![None; len];
+    let mut free = Free::new(len);
+
+    for (c, &first) in data[..256].iter().enumerate() {
+        if first > 0 {
+            let pos = first as usize - 1;
+            match out.get_mut(pos) {
+                Some(slot @ &mut None) => *slot = Some(c as u8),
+                _ => return Err(String::from("Distance coded block has a bad first position")),
+            };
+            free.take(pos);
+        };
+    };
+
+    for (i, &dist) in data[256..].iter().enumerate() {
+        let c = out[i].ok_or_else(|| String::from("Distance coded block leaves a gap"))?;
+        if dist == 0 {
+            continue;
+        };
+
+        let j = free.nth(free.before(i + 1) + dist as usize - 1).
+            ok_or_else(|| String::from("Distance coded block points past its end"))?;
+        out[j] = Some(c);
+        free.take(j);
+    };
+
+    Ok(out.into_iter().map(|c| c.unwrap()).collect())
+}
+
+/// The inversion frequencies of `data`: how often each byte value occurs, then for each value,
+/// how many larger bytes come before each of its occurrences and after the one before.
+pub fn encode_inversions(data: &[u8]) -> Vec<u32> {
+    let mut counts = [0u32; 256];
+    for &c in data {
+        counts[c as usize] += 1;
+    };
+
+    let mut positions = vec![Vec::new(); 256];
+    for (i, &c) in data.iter().enumerate() {
+        positions[c as usize].push(i);
+    };
+
+    let mut out = counts.to_vec();
+    out.reserve(data.len());
+
+    // Positions of bytes smaller than the current one are taken out as it goes.
+    let mut free = Free::new(data.len());
+    for positions in &positions {
+        let mut from = 0;
+        for &pos in positions {
+            let before = free.before(pos);
+            out.push((before - from) as u32);
+            from = before + 1;
+        };
+
+        for &pos in positions {
+            free.take(pos);
+        };
+    };
+
+    out
+}
+
+pub fn decode_inversions(data: &[u32]) -> Result<Vec<u8>, String> {
+    if data.len() < 256 {
+        return Err(String::from("Inversion coded block is too short"));
+    };
+    let counts = &data[..256];
+    let mut gaps = data[256..].iter();
+
+    // Every byte has a gap, so the counts can't claim a longer block than there's input for.
+    let len = gaps.len();
+    if counts.iter().map(|&count| count as u64).sum::<u64>() != len as u64 {
+        return Err(String::from("Inversion coded block has the wrong counts"));
+    };
+
+    let mut out = vec![0; len];
+    let mut free = Free::new(len);
+
+    for (c, &count) in counts.iter().enumerate() {
+        let mut taken = Vec::with_capacity(count as usize);
+        let mut rank = 0;
+
+        for &gap in gaps.by_ref().take(count as usize) {
+            rank += gap as usize;
+            let pos = free.nth(rank).
+                ok_or_else(|| String::from("Inversion coded block points past its end"))?;
+            out[pos] = c as u8;
+            taken.push(pos);
+            rank += 1;
+        };
+
+        for pos in taken {
+            free.take(pos);
+        };
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use distance;
+    use mtf;
+    use range;
+    use rle;
+    use testdata;
+
+    #[test]
+    fn distances() {
+        // b is known at 1, n at 2 and a at 0; then each a, n and b points on to its next.
+        let encoded = distance::encode_distances(b"abnana");
+        let mut expected = vec![0; 256];
+        expected[b'a' as usize] = 1;
+        expected[b'b' as usize] = 2;
+        expected[b'n' as usize] = 3;
+        expected.extend_from_slice(&[1, 0, 1, 1, 0, 0]);
+
+        assert_eq!(encoded, expected);
+        assert_eq!(distance::decode_distances(&encoded).unwrap(), b"abnana");
+    }
+
+    #[test]
+    fn inversions() {
+        // Two larger bytes before the second a and one before the third, none before b, and
+        // none before either n, the largest.
+        let encoded = distance::encode_inversions(b"abnana");
+        let mut expected = vec![0; 256];
+        expected[b'a' as usize] = 3;
+        expected[b'b' as usize] = 1;
+        expected[b'n' as usize] = 2;
+        expected.extend_from_slice(&[0, 2, 1, 0, 0, 0]);
+
+        assert_eq!(encoded, expected);
+        assert_eq!(distance::decode_inversions(&encoded).unwrap(), b"abnana");
+    }
+
+    #[test]
+    fn round_trip() {
+        let source: &[u8] = include_bytes!("distance.rs");
+
+        for &input in testdata::SAMPLES.iter().chain(&[source]) {
+            let bwt = bwt::encode(input);
+
+            let distances = distance::encode_distances(&bwt);
+            let inversions = distance::encode_inversions(&bwt);
+
+            assert_eq!(distance::decode_distances(&distances).unwrap(), bwt);
+            assert_eq!(distance::decode_inversions(&inversions).unwrap(), bwt);
+        };
+
+        let mut huge = vec![0; 256];
+        huge[255] = u32::MAX;
+
+        assert!(distance::decode_distances(&[0; 10]).is_err());
+        assert!(distance::decode_inversions(&[1; 256]).is_err());
+        assert!(distance::decode_inversions(&huge).is_err());
+    }
+
+    // Sizes after each way of coding the BWT, on `testdata::corpus`:
+    // CORPUS=<file or directory> cargo test --release compare_with_mtf -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn compare_with_mtf() {
+        let data = testdata::corpus();
+
+        for block in data.chunks(900_000) {
+            let bwt = bwt::encode(block);
+            let mtf = range::encode(&rle::encode(&mtf::encode(&bwt))).len();
+            let distances = range::encode_gaps(&distance::encode_distances(&bwt)).len();
+            let inversions = range::encode_gaps(&distance::encode_inversions(&bwt)).len();
+
+            println!("{} bytes: mtf {}, distances {}, inversions {}", block.len(), mtf,
+                     distances, inversions);
+        };
+    }
+}
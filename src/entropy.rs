@@ -0,0 +1,198 @@
+use bitstream::Bitstream;
+use cm;
+use huffman;
+use huffman::{HuffmanData, TableSet};
+use range;
+use rans;
+use std::io;
+use std::io::Write;
+use std::io::Read;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// The entropy coder used for a block.  This is written as the first byte of each block, so
+/// blocks in one file can use different backends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// Semi-static Huffman coding with a table per block.
+    Huffman,
+    /// One-pass adaptive Huffman coding; no table is sent.
+    AdaptiveHuffman,
+    /// Range coding with an adaptive order-0 model.
+    Range,
+    /// Static rANS with two interleaved states and a table per block.
+    Rans,
+    /// Binary arithmetic coding with contexts built from the preceding ranks and runs.  The
+    /// slowest backend, and the strongest on the output of `mtf::encode` and `rle::encode`.
+    Context,
+    /// Huffman coding with tables chosen by the previous byte.  Meant for data that hasn't been
+    /// through the BWT.
+    Order1Huffman,
+    /// No coding at all, for blocks the transform has already entropy coded.
+    Stored,
+}
+
+impl Backend {
+    fn tag(self) -> u8 {
+        match self {
+            Backend::Huffman => 0,
+            Backend::AdaptiveHuffman => 1,
+            Backend::Range => 2,
+            Backend::Rans => 3,
+            Backend::Context => 4,
+            Backend::Order1Huffman => 5,
+            Backend::Stored => 6,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Backend> {
+        match tag {
+            0 => Some(Backend::Huffman),
+            1 => Some(Backend::AdaptiveHuffman),
+            2 => Some(Backend::Range),
+            3 => Some(Backend::Rans),
+            4 => Some(Backend::Context),
+            5 => Some(Backend::Order1Huffman),
+            6 => Some(Backend::Stored),
+            _ => None,
+        }
+    }
+}
+
+fn invalid<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+// Backends that produce plain bytes write them with a length in front.
+fn write_bytes(bytes: &[u8], writer: &mut dyn Write) -> io::Result<usize> {
+    writer.write_u32::<BigEndian>(bytes.len() as u32)?;
+    writer.write_all(bytes)?;
+    Ok(4 + bytes.len())
+}
+
+fn read_bytes(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32::<BigEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Writes blocks with one backend, keeping whatever state it carries between blocks.
+pub struct BlockEncoder {
+    backend: Backend,
+    huffman: huffman::Encoder,
+}
+
+impl BlockEncoder {
+    pub fn new(backend: Backend) -> BlockEncoder {
+        BlockEncoder { backend, huffman: huffman::Encoder::new() }
+    }
+
+    pub fn write_block(&mut self, data: &[u8], writer: &mut dyn Write) -> io::Result<usize> {
+        writer.write_u8(self.backend.tag())?;
+
+        let bytes_out = match self.backend {
+            Backend::Huffman => self.huffman.encode(data).map_err(invalid)?.write(writer)?,
+            Backend::AdaptiveHuffman => huffman::encode_adaptive(data).write(writer)?,
+            Backend::Range => write_bytes(&range::encode(data), writer)?,
+            Backend::Rans => write_bytes(&rans::encode(data, 2), writer)?,
+            Backend::Context => write_bytes(&cm::encode(data), writer)?,
+            Backend::Order1Huffman =>
+                write_bytes(&huffman::encode_order1(data).map_err(invalid)?, writer)?,
+            Backend::Stored => write_bytes(data, writer)?,
+        };
+
+        Ok(1 + bytes_out)
+    }
+}
+
+/// Reads blocks written by a `BlockEncoder`, whichever backend each one uses.
+#[derive(Default)]
+pub struct BlockDecoder {
+    tables: TableSet,
+}
+
+impl BlockDecoder {
+    pub fn new() -> BlockDecoder {
+        BlockDecoder::with_tables(TableSet::new())
+    }
+
+    /// A decoder that knows about some static Huffman tables.
+    pub fn with_tables(tables: TableSet) -> BlockDecoder {
+        BlockDecoder { tables }
+    }
+
+    pub fn read_block(&mut self, reader: &mut dyn Read) -> io::Result<Option<Vec<u8>>> {
+        let tag = match reader.read_u8() {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(tag) => tag,
+        };
+
+        let data = match Backend::from_tag(tag) {
+            Some(Backend::Huffman) =>
+                match HuffmanData::read_next(reader, &mut self.tables)? {
+                    Some(hd) => huffman::decode(&hd).map_err(invalid)?,
+                    None => return Err(invalid("Truncated block")),
+                },
+            Some(Backend::AdaptiveHuffman) =>
+                match Bitstream::read(reader)? {
+                    Some(bs) => huffman::decode_adaptive(&bs).map_err(invalid)?,
+                    None => return Err(invalid("Truncated block")),
+                },
+            Some(Backend::Range) => range::decode(&read_bytes(reader)?).map_err(invalid)?,
+            Some(Backend::Rans) => rans::decode(&read_bytes(reader)?).map_err(invalid)?,
+            Some(Backend::Context) => cm::decode(&read_bytes(reader)?).map_err(invalid)?,
+            Some(Backend::Order1Huffman) =>
+                huffman::decode_order1(&read_bytes(reader)?).map_err(invalid)?,
+            Some(Backend::Stored) => read_bytes(reader)?,
+            None => return Err(invalid(format!("Unknown entropy backend {}", tag))),
+        };
+
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use entropy::{Backend, BlockDecoder, BlockEncoder};
+
+    fn round_trip(backend: Backend, blocks: &[&[u8]]) -> usize {
+        let mut encoder = BlockEncoder::new(backend);
+        let mut buf = Vec::new();
+
+        for block in blocks {
+            encoder.write_block(block, &mut buf).unwrap();
+        };
+
+        let mut decoder = BlockDecoder::new();
+        let mut reader = &buf[..];
+
+        for block in blocks {
+            assert_eq!(decoder.read_block(&mut reader).unwrap().unwrap(), *block);
+        };
+        assert!(decoder.read_block(&mut reader).unwrap().is_none());
+
+        buf.len()
+    }
+
+    #[test]
+    fn backends() {
+        let blocks: [&[u8]; 3] = [b"abracadabra", b"", b"this is a test."];
+
+        round_trip(Backend::Huffman, &blocks);
+        round_trip(Backend::AdaptiveHuffman, &blocks);
+        round_trip(Backend::Range, &blocks);
+        round_trip(Backend::Rans, &blocks);
+        round_trip(Backend::Context, &blocks);
+        round_trip(Backend::Order1Huffman, &blocks);
+        round_trip(Backend::Stored, &blocks);
+    }
+
+    #[test]
+    fn adaptive_needs_no_table() {
+        let block: &[u8] = b"a short message, a short message";
+
+        assert!(round_trip(Backend::AdaptiveHuffman, &[block]) < block.len());
+        assert!(round_trip(Backend::Huffman, &[block]) > 512);
+    }
+}
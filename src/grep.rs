@@ -0,0 +1,184 @@
+// Searching compressed streams for a literal pattern, a line at a time.  Blocks are read one after
+// another and lines are put back together across block boundaries, so a line, or a match, split
+// between two blocks is still found.
+//
+// Blocks stored with suffix array samples are searched through their FM-index.  One with no
+// match inside it only needs its first and last lines extracted, in case they join up with the
+// blocks on either side.
+use bwt::FmIndex;
+use pipeline::{Block, Decompressor};
+use std::io;
+use std::io::Write;
+use std::io::Read;
+
+// How much to extract at first when looking for the end of a block's first or last line.
+const FIRST_EXTRACT: usize = 64;
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    };
+
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+struct Lines<'a> {
+    pattern: &'a [u8],
+    out: &'a mut dyn Write,
+    // The line so far and where it starts in the stream.
+    line: Vec<u8>,
+    line_start: usize,
+    block_starts: Vec<usize>,
+    matches: usize,
+}
+
+impl<'a> Lines<'a> {
+    // Takes in `bytes`, which start at `at` in the stream.  They must carry on from the bytes fed
+    // before unless those ended a line.
+    fn feed(&mut self, bytes: &[u8], at: usize) -> io::Result<()> {
+        if self.line.is_empty() {
+            self.line_start = at;
+        };
+
+        let mut from = 0;
+        for (i, _) in bytes.iter().enumerate().filter(|&(_, &b)| b == b'\n') {
+            self.line.extend_from_slice(&bytes[from..i]);
+            self.finish_line()?;
+            from = i + 1;
+            self.line_start = at + from;
+        };
+        self.line.extend_from_slice(&bytes[from..]);
+
+        Ok(())
+    }
+
+    fn finish_line(&mut self) -> io::Result<()> {
+        if let Some(pos) = find(&self.line, self.pattern) {
+            let offset = self.line_start + pos;
+            let block = self.block_starts.partition_point(|&start| start <= offset) - 1;
+
+            writeln!(self.out, "{}:{}+{}:{}", offset, block, offset - self.block_starts[block],
+                     String::from_utf8_lossy(&self.line))?;
+            self.matches += 1;
+        };
+
+        self.line.clear();
+        Ok(())
+    }
+
+    // Feeds in an indexed block, extracting only what might hold a match.
+    fn feed_index(&mut self, index: &FmIndex, at: usize) -> io::Result<()> {
+        let len = index.len();
+        if index.count(self.pattern) > 0 {
+            return self.feed(&index.extract(0, len), at);
+        };
+
+        // Nothing matches within the block, but its first and last lines might still, once
+        // joined to the blocks around it.
+        let mut first = Vec::new();
+        let mut size = FIRST_EXTRACT;
+        while first.len() < len && !first.contains(&b'\n') {
+            first = index.extract(0, size.min(len));
+            size *= 2;
+        };
+
+        let first_end = match first.iter().position(|&b| b == b'\n') {
+            Some(end) => end + 1,
+            None => return self.feed(&first, at),
+        };
+        self.feed(&first[..first_end], at)?;
+
+        let mut last = Vec::new();
+        let mut size = FIRST_EXTRACT;
+        while last.len() < len - first_end && !last.contains(&b'\n') {
+            last = index.extract(len - size.min(len - first_end), len);
+            size *= 2;
+        };
+
+        let last_start = last.iter().rposition(|&b| b == b'\n').map_or(0, |nl| nl + 1);
+        self.feed(&last[last_start..], at + len - (last.len() - last_start))
+    }
+}
+
+/// Writes each line of the stream in `reader` that contains `pattern` to `out`, after the offset
+/// of the first match in the stream, its block and its offset in the block, as
+/// `offset:block+offset:line`.  Returns the number of lines written.
+pub fn grep(pattern: &[u8], reader: &mut dyn Read, out: &mut dyn Write) -> io::Result<usize> {
+    let mut decompressor = Decompressor::new();
+    let mut lines = Lines {
+        pattern,
+        out,
+        line: Vec::new(),
+        line_start: 0,
+        block_starts: Vec::new(),
+        matches: 0,
+    };
+    let mut at = 0;
+
+    while let Some(block) = decompressor.read_searchable(reader)? {
+        lines.block_starts.push(at);
+
+        at += match block {
+            Block::Decoded(data) => {
+                lines.feed(&data, at)?;
+                data.len()
+            },
+            Block::Indexed(index) => {
+                lines.feed_index(&index, at)?;
+                index.len()
+            },
+        };
+    };
+
+    if !lines.line.is_empty() {
+        lines.finish_line()?;
+    };
+
+    Ok(lines.matches)
+}
+
+#[cfg(test)]
+mod test {
+    use grep::grep;
+    use pipeline::{compress, Options};
+
+    fn grep_lines(data: &[u8], pattern: &[u8], options: Options) -> String {
+        let mut buf = Vec::new();
+        compress(data, options, &mut buf).unwrap();
+
+        let mut out = Vec::new();
+        let matches = grep(pattern, &mut &buf[..], &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(out.lines().count(), matches);
+        out
+    }
+
+    #[test]
+    fn across_blocks() {
+        let data = b"first line\nsecond needle line\nthird line with a needle\nlast";
+
+        for &sample_rate in &[0, 4] {
+            // Blocks of 20 bytes split the first "needle" and the third line.
+            let options = Options { block_size: 20, sample_rate, ..Options::default() };
+
+            assert_eq!(grep_lines(data, b"needle", options),
+                       "18:0+18:second needle line\n48:2+8:third line with a needle\n");
+            assert_eq!(grep_lines(data, b"zebra", options), "");
+            assert_eq!(grep_lines(data, b"last", options), "55:2+15:last\n");
+        };
+    }
+
+    #[test]
+    fn matches_decoded_search() {
+        let data = include_bytes!("grep.rs");
+        let expected = data.split(|&b| b == b'\n').
+            filter(|line| line.windows(4).any(|w| w == b"line")).
+            count();
+
+        for &(block_size, sample_rate) in &[(100_000, 0), (700, 0), (700, 8), (200, 8)] {
+            let options = Options { block_size, sample_rate, ..Options::default() };
+            assert_eq!(grep_lines(data, b"line", options).lines().count(), expected);
+        };
+    }
+}
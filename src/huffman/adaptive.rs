@@ -0,0 +1,310 @@
+// One-pass adaptive Huffman coding (FGK).  Both sides start from a tree holding only the
+// not-yet-transmitted (NYT) leaf and update it identically after every symbol, so no table is sent.
+// The first occurrence of a symbol is coded as the path to NYT followed by the symbol's 8 bits,
+// most significant first.
+use bitstream::Bitstream;
+
+const NONE: usize = usize::MAX;
+
+#[derive(Clone, Copy)]
+struct Slot {
+    weight: usize,
+    parent: usize,
+    left: usize,
+    right: usize,
+    // Only meaningful for leaves other than NYT.
+    sym: u8,
+}
+
+// Nodes are kept in order of decreasing implicit number, so the root is slot 0 and weights never
+// increase along the slots (the sibling property).
+#[derive(Clone)]
+struct Tree {
+    slots: Vec<Slot>,
+    leaves: [usize; 256],
+    nyt: usize,
+}
+
+impl Tree {
+    fn new() -> Tree {
+        let root = Slot { weight: 0, parent: NONE, left: NONE, right: NONE, sym: 0 };
+        let mut slots = Vec::with_capacity(2 * 257 - 1);
+        slots.push(root);
+
+        Tree { slots, leaves: [NONE; 256], nyt: 0 }
+    }
+
+    fn is_leaf(&self, node: usize) -> bool {
+        self.slots[node].left == NONE
+    }
+
+    // Appends the path from the root to `node`.
+    fn append_path(&self, mut node: usize, out: &mut Bitstream) {
+        let mut path = Vec::new();
+
+        while self.slots[node].parent != NONE {
+            let parent = self.slots[node].parent;
+            path.push(if self.slots[parent].right == node { 1 } else { 0 });
+            node = parent;
+        };
+
+        for &bit in path.iter().rev() {
+            out.append(bit);
+        };
+    }
+
+    // Swaps the subtrees hanging in two slots.  Each slot keeps its own parent.
+    fn swap(&mut self, a: usize, b: usize) {
+        let (parent_a, parent_b) = (self.slots[a].parent, self.slots[b].parent);
+        self.slots.swap(a, b);
+        self.slots[a].parent = parent_a;
+        self.slots[b].parent = parent_b;
+
+        if self.nyt == a {
+            self.nyt = b;
+        } else if self.nyt == b {
+            self.nyt = a;
+        };
+
+        for &node in &[a, b] {
+            let slot = self.slots[node];
+            if slot.left != NONE {
+                self.slots[slot.left].parent = node;
+                self.slots[slot.right].parent = node;
+            } else if node != self.nyt {
+                self.leaves[slot.sym as usize] = node;
+            };
+        };
+    }
+
+    fn update(&mut self, sym: u8) {
+        let mut node = self.leaves[sym as usize];
+
+        if node == NONE {
+            // Split NYT into a new NYT on the left and the symbol's leaf on the right.  The new NYT
+            // has the lowest number of all.
+            let old_nyt = self.nyt;
+            let leaf = self.slots.len();
+            let nyt = leaf + 1;
+
+            self.slots.push(Slot { weight: 0, parent: old_nyt, left: NONE, right: NONE, sym });
+            self.slots.push(Slot { weight: 0, parent: old_nyt, left: NONE, right: NONE, sym: 0 });
+            self.slots[old_nyt].left = nyt;
+            self.slots[old_nyt].right = leaf;
+
+            self.leaves[sym as usize] = leaf;
+            self.nyt = nyt;
+            node = leaf;
+        };
+
+        loop {
+            let weight = self.slots[node].weight;
+            let leader = self.slots[..node].partition_point(|s| s.weight > weight);
+
+            if leader != node && leader != self.slots[node].parent {
+                self.swap(leader, node);
+                node = leader;
+            };
+
+            self.slots[node].weight += 1;
+
+            match self.slots[node].parent {
+                NONE => break,
+                parent => node = parent,
+            };
+        };
+    }
+}
+
+/// Codes symbols as they arrive, appending each code to the output straight away.
+#[derive(Clone)]
+pub struct AdaptiveEncoder {
+    tree: Tree,
+}
+
+impl Default for AdaptiveEncoder {
+    fn default() -> AdaptiveEncoder {
+        AdaptiveEncoder::new()
+    }
+}
+
+impl AdaptiveEncoder {
+    pub fn new() -> AdaptiveEncoder {
+        AdaptiveEncoder { tree: Tree::new() }
+    }
+
+    pub fn push(&mut self, data: &[u8], out: &mut Bitstream) {
+        for &c in data {
+            match self.tree.leaves[c as usize] {
+                NONE => {
+                    self.tree.append_path(self.tree.nyt, out);
+                    for i in (0..8).rev() {
+                        out.append(c >> i);
+                    };
+                },
+                leaf => self.tree.append_path(leaf, out),
+            };
+
+            self.tree.update(c);
+        };
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    // Walking down the tree from this node.
+    Walk(usize),
+    // Reading the raw bits of a new symbol: how many so far, and their value.
+    Raw(usize, u8),
+}
+
+/// The inverse of `AdaptiveEncoder`.  Input may be split anywhere, even inside a code.
+#[derive(Clone)]
+pub struct AdaptiveDecoder {
+    tree: Tree,
+    state: State,
+}
+
+impl Default for AdaptiveDecoder {
+    fn default() -> AdaptiveDecoder {
+        AdaptiveDecoder::new()
+    }
+}
+
+impl AdaptiveDecoder {
+    pub fn new() -> AdaptiveDecoder {
+        AdaptiveDecoder { tree: Tree::new(), state: State::Raw(0, 0) }
+    }
+
+    fn start_state(&self) -> State {
+        if self.tree.is_leaf(0) { State::Raw(0, 0) } else { State::Walk(0) }
+    }
+
+    fn emit(&mut self, sym: u8, out: &mut Vec<u8>) {
+        out.push(sym);
+        self.tree.update(sym);
+        self.state = self.start_state();
+    }
+
+    /// Decodes all of `bits`, appending the symbols that are complete.
+    pub fn push(&mut self, bits: &mut Bitstream, out: &mut Vec<u8>) {
+        while let Some(bit) = bits.pop_start() {
+            match self.state {
+                State::Raw(count, val) => {
+                    let val = (val << 1) | bit;
+                    if count == 7 {
+                        self.emit(val, out);
+                    } else {
+                        self.state = State::Raw(count + 1, val);
+                    };
+                },
+                State::Walk(node) => {
+                    let slot = self.tree.slots[node];
+                    let next = if bit == 0 { slot.left } else { slot.right };
+
+                    if next == self.tree.nyt {
+                        self.state = State::Raw(0, 0);
+                    } else if self.tree.is_leaf(next) {
+                        let sym = self.tree.slots[next].sym;
+                        self.emit(sym, out);
+                    } else {
+                        self.state = State::Walk(next);
+                    };
+                },
+            };
+        };
+    }
+
+    /// Checks that the input ended between two symbols.
+    pub fn finish(&self) -> Result<(), String> {
+        match (self.state, self.start_state()) {
+            (State::Walk(0), State::Walk(0)) | (State::Raw(0, _), State::Raw(0, _)) => Ok(()),
+            _ => Err(String::from("Bitstream ends inside a code")),
+        }
+    }
+}
+
+pub fn encode_adaptive(data: &[u8]) -> Bitstream {
+    let mut bs = Bitstream::new();
+    AdaptiveEncoder::new().push(data, &mut bs);
+    bs
+}
+
+pub fn decode_adaptive(bs: &Bitstream) -> Result<Vec<u8>, String> {
+    let mut decoder = AdaptiveDecoder::new();
+    let mut out = Vec::new();
+
+    decoder.push(&mut bs.clone(), &mut out);
+    decoder.finish()?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bitstream::Bitstream;
+    use huffman::{decode_adaptive, encode_adaptive, AdaptiveDecoder, AdaptiveEncoder};
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 4] = [b"", b"a", b"abracadabra", b"this is a test."];
+
+        for &input in inputs.iter() {
+            assert_eq!(decode_adaptive(&encode_adaptive(input)).unwrap(), input);
+        };
+
+        let all = (0..=255).chain((0..=255).rev()).cycle().take(5000).collect::<Vec<u8>>();
+        assert_eq!(decode_adaptive(&encode_adaptive(&all)).unwrap(), all);
+    }
+
+    #[test]
+    fn codes() {
+        // "a" is raw; the second "a" is the path to its leaf, which is "1".
+        assert_eq!(format!("{}", encode_adaptive(b"aa")), "011000011");
+        // "b" is the path to NYT, "0", and then raw.
+        assert_eq!(format!("{}", encode_adaptive(b"ab")), "01100001001100010");
+    }
+
+    #[test]
+    fn skewed() {
+        let mut data = vec![0; 10_000];
+        data.extend_from_slice(b"rare bytes");
+
+        let bs = encode_adaptive(&data);
+
+        assert!(bs.len() < 11_000);
+        assert_eq!(decode_adaptive(&bs).unwrap(), data);
+    }
+
+    #[test]
+    fn split_input() {
+        let data = b"abracadabra, abracadabra!";
+        let mut encoder = AdaptiveEncoder::new();
+        let mut bs = Bitstream::new();
+
+        for chunk in data.chunks(3) {
+            encoder.push(chunk, &mut bs);
+        };
+
+        let mut decoder = AdaptiveDecoder::new();
+        let mut out = Vec::new();
+        let mut bs = bs.clone();
+
+        while !bs.is_empty() {
+            let mut piece = Bitstream::new();
+            for _ in 0..5 {
+                if let Some(bit) = bs.pop_start() {
+                    piece.append(bit);
+                };
+            };
+            decoder.push(&mut piece, &mut out);
+        };
+
+        decoder.finish().unwrap();
+        assert_eq!(&out[..], &data[..]);
+
+        let mut truncated = encode_adaptive(data);
+        truncated.pop();
+        assert!(decode_adaptive(&truncated).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use bitstream::Bitstream;
+use huffman::encode::precalc_bitstreams;
+use std::io;
+use std::io::Write;
+use std::io::Read;
+
+/// The code assigned to each byte value.  Symbols without a code can't be encoded.
+#[derive(Clone, Debug)]
+pub struct CodeBook {
+    codes: Vec<Option<Bitstream>>,
+}
+
+// A node in the decoding trie.  Children index into the trie; `None` means no code goes that way.
+#[derive(Clone, Copy)]
+pub(super) enum Branch {
+    Inner([Option<usize>; 2]),
+    Leaf(u8),
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl CodeBook {
+    /// The codes `huffman::encode` uses for a block with these frequencies.
+    pub fn from_freqs(freqs: &[usize; 256]) -> CodeBook {
+        CodeBook { codes: precalc_bitstreams(freqs).unwrap() }
+    }
+
+    /// Canonical codes for the given lengths.  A length of 0 means the symbol has no code.  Fails
+    /// if the lengths can't all fit in a prefix code.
+    pub fn from_lengths(lengths: &[u8; 256]) -> Result<CodeBook, String> {
+        let mut order = (0..256).filter(|&i| lengths[i] > 0).collect::<Vec<usize>>();
+        order.sort_by_key(|&i| (lengths[i], i));
+
+        let mut codes = vec![None; 256];
+        let mut code: Vec<u8> = Vec::new();
+
+        for (n, &sym) in order.iter().enumerate() {
+            if n > 0 {
+                // Add one to the previous code, carrying from the last bit.
+                let mut pos = code.len();
+                loop {
+                    if pos == 0 {
+                        return Err(String::from("Code lengths are oversubscribed"));
+                    };
+                    pos -= 1;
+                    code[pos] ^= 1;
+                    if code[pos] == 1 {
+                        break;
+                    };
+                };
+            };
+
+            code.resize(lengths[sym] as usize, 0);
+
+            let mut bs = Bitstream::new();
+            for &bit in &code {
+                bs.append(bit);
+            };
+            codes[sym] = Some(bs);
+        };
+
+        Ok(CodeBook { codes })
+    }
+
+    pub fn code(&self, sym: u8) -> Option<&Bitstream> {
+        self.codes[sym as usize].as_ref()
+    }
+
+    /// Length of the code for `sym` in bits, or 0 if it has none.
+    pub fn length(&self, sym: u8) -> usize {
+        self.codes[sym as usize].as_ref().map_or(0, |bs| bs.len())
+    }
+
+    /// Bits per symbol when coding data with these frequencies.
+    pub fn average_length(&self, freqs: &[usize; 256]) -> f64 {
+        let total: usize = freqs.iter().sum();
+        if total == 0 {
+            return 0.0;
+        };
+
+        let bits: usize = (0..256).map(|i| freqs[i] * self.length(i as u8)).sum();
+
+        bits as f64 / total as f64
+    }
+
+    /// Order-0 entropy of the frequencies in bits per symbol; the lower bound for
+    /// `average_length`.
+    pub fn entropy(freqs: &[usize; 256]) -> f64 {
+        let total = freqs.iter().sum::<usize>() as f64;
+
+        freqs.iter().
+            filter(|&&f| f > 0).
+            map(|&f| f as f64 / total).
+            map(|p| -p * p.log2()).
+            sum()
+    }
+
+    pub fn encode(&self, data: &[u8]) -> Result<Bitstream, String> {
+        let mut bs = Bitstream::new();
+
+        for &c in data {
+            match self.codes[c as usize] {
+                Some(ref code) => bs.append_bitstream(code),
+                None => return Err(format!("No code for symbol {}", c)),
+            };
+        };
+
+        Ok(bs)
+    }
+
+    pub(super) fn build_trie(&self) -> Result<Vec<Branch>, String> {
+        let mut trie = vec![Branch::Inner([None, None])];
+
+        for (sym, code) in self.codes.iter().enumerate() {
+            let mut code = match *code {
+                Some(ref code) => code.clone(),
+                None => continue,
+            };
+
+            let mut idx = 0;
+            while let Some(bit) = code.pop_start() {
+                let next = trie.len();
+                idx = match trie[idx] {
+                    Branch::Inner(ref mut children) =>
+                        *children[bit as usize].get_or_insert(next),
+                    Branch::Leaf(_) => return Err(format!("Code for {} has a prefix", sym)),
+                };
+
+                if idx == next {
+                    trie.push(Branch::Inner([None, None]));
+                };
+            };
+
+            match trie[idx] {
+                Branch::Inner([None, None]) if idx != 0 => trie[idx] = Branch::Leaf(sym as u8),
+                _ => return Err(format!("Code for {} is a prefix of another", sym)),
+            };
+        };
+
+        Ok(trie)
+    }
+
+    // Reads one symbol from the front of `bs` with a trie from `build_trie`, or `None` if `bs` is
+    // empty.
+    pub(super) fn decode_symbol(trie: &[Branch], bs: &mut Bitstream) -> Result<Option<u8>, String> {
+        let mut idx = 0;
+
+        loop {
+            match trie[idx] {
+                Branch::Leaf(val) => return Ok(Some(val)),
+                Branch::Inner(children) =>
+                    match bs.pop_start() {
+                        Some(bit) => {
+                            idx = match children[bit as usize] {
+                                Some(child) => child,
+                                None => return Err(String::from("Bits match no code")),
+                            };
+                        },
+                        None if idx == 0 => return Ok(None),
+                        None => return Err(String::from("Bitstream ends inside a code")),
+                    },
+            };
+        };
+    }
+
+    pub fn decode(&self, bs: &Bitstream) -> Result<Vec<u8>, String> {
+        let trie = self.build_trie()?;
+        let mut s = bs.clone();
+        let mut acc = Vec::new();
+
+        while let Some(val) = CodeBook::decode_symbol(&trie, &mut s)? {
+            acc.push(val);
+        };
+
+        Ok(acc)
+    }
+
+    // Each symbol is written as its code length in bits, followed by the code packed into bytes.
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        let mut bytes_out = 0;
+
+        for code in &self.codes {
+            match *code {
+                Some(ref code) => {
+                    let byte_len = code.len().div_ceil(8);
+                    writer.write_all(&[code.len() as u8])?;
+                    writer.write_all(&code.data[0..byte_len])?;
+                    bytes_out += 1 + byte_len;
+                },
+                None => {
+                    writer.write_all(&[0])?;
+                    bytes_out += 1;
+                },
+            };
+        };
+
+        Ok(bytes_out)
+    }
+
+    pub fn read(reader: &mut dyn Read) -> io::Result<CodeBook> {
+        let mut codes = Vec::with_capacity(256);
+
+        for _ in 0..256 {
+            let mut len = [0];
+            reader.read_exact(&mut len)?;
+
+            let len = len[0] as usize;
+            if len == 0 {
+                codes.push(None);
+                continue;
+            };
+
+            let mut data = vec![0; len.div_ceil(8)];
+            reader.read_exact(&mut data)?;
+
+            let mut bs = Bitstream::new();
+            for i in 0..len {
+                bs.append(data[i >> 3] >> (i & 7));
+            };
+            codes.push(Some(bs));
+        };
+
+        let book = CodeBook { codes };
+        match book.build_trie() {
+            Ok(_) => Ok(book),
+            Err(err) => Err(invalid(&err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use huffman::{build_freqs, CodeBook};
+
+    #[test]
+    fn matches_encoder() {
+        let data = b"abracadabra";
+        let book = CodeBook::from_freqs(&build_freqs(data));
+        let bs = book.encode(data).unwrap();
+
+        assert_eq!(bs.data, ::huffman::encode(data).unwrap().bs.data);
+        assert_eq!(book.decode(&bs).unwrap(), data);
+        assert_eq!(book.length(b'a'), 1);
+    }
+
+    #[test]
+    fn canonical_lengths() {
+        let mut lengths = [0; 256];
+        lengths[b'a' as usize] = 1;
+        lengths[b'b' as usize] = 2;
+        lengths[b'c' as usize] = 3;
+        lengths[b'd' as usize] = 3;
+
+        let book = CodeBook::from_lengths(&lengths).unwrap();
+
+        assert_eq!(format!("{}", book.code(b'a').unwrap()), "0");
+        assert_eq!(format!("{}", book.code(b'b').unwrap()), "10");
+        assert_eq!(format!("{}", book.code(b'c').unwrap()), "110");
+        assert_eq!(format!("{}", book.code(b'd').unwrap()), "111");
+        assert!(book.code(b'e').is_none());
+        assert!(book.encode(b"e").is_err());
+
+        let bs = book.encode(b"dcba").unwrap();
+        assert_eq!(book.decode(&bs).unwrap(), b"dcba");
+
+        lengths[b'e' as usize] = 3;
+        assert!(CodeBook::from_lengths(&lengths).is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let mut freqs = [0; 256];
+        freqs[0] = 2;
+        freqs[1] = 1;
+        freqs[2] = 1;
+
+        let mut lengths = [0; 256];
+        lengths[0] = 1;
+        lengths[1] = 2;
+        lengths[2] = 2;
+
+        let book = CodeBook::from_lengths(&lengths).unwrap();
+
+        assert_eq!(CodeBook::entropy(&freqs), 1.5);
+        assert_eq!(book.average_length(&freqs), 1.5);
+
+        // Unused symbols still get leaves in the encoder's tree, which costs a little.
+        let book = CodeBook::from_freqs(&freqs);
+        assert_eq!(book.average_length(&freqs), 1.75);
+    }
+
+    #[test]
+    fn write_read() {
+        let data = b"this is a test.";
+        let book = CodeBook::from_freqs(&build_freqs(data));
+
+        let mut buf = Vec::new();
+        let len = book.write(&mut buf).unwrap();
+        assert_eq!(len, buf.len());
+
+        let read = CodeBook::read(&mut &buf[..]).unwrap();
+        let bs = book.encode(data).unwrap();
+
+        assert_eq!(read.decode(&bs).unwrap(), data);
+        assert!(CodeBook::read(&mut &buf[..10]).is_err());
+    }
+}
@@ -10,7 +10,7 @@ enum State<'a> {
 
 // we could keep this around between blocks.  we would need to check if the new max is higher, and
 // add new elements as needed
-fn precalc_bitstreams(freqs: &[usize; 256]) -> Result<Vec<Option<Bitstream>>,()> {
+pub(super) fn precalc_bitstreams(freqs: &[usize; 256]) -> Result<Vec<Option<Bitstream>>,()> {
     // TODO: byte-wise table rather than bit-
     // https://www.reddit.com/r/rust/comments/54jlxf/huffman_coding_implementation_in_rust/d82frgt/
     let root = huffman::build_tree(freqs);
@@ -33,7 +33,7 @@ fn precalc_bitstreams(freqs: &[usize; 256]) -> Result<Vec<Option<Bitstream>>,()>
 
                     State::Right(&Node::Leaf { val, .. }) | State::Left(&Node::Leaf { val, .. }) => values[val as usize] = Some(acc.clone()),
 
-                    State::Right(&Node::Tree { ref right, .. }) => {
+                    State::Right(Node::Tree { right, .. }) => {
                         let next_node = right.as_ref();
 
                         acc.pop();
@@ -42,7 +42,7 @@ fn precalc_bitstreams(freqs: &[usize; 256]) -> Result<Vec<Option<Bitstream>>,()>
                         history.push(State::Left(next_node));
                     },
 
-                    State::Left(node @ &Node::Tree { .. }) => {
+                    State::Left(node @ Node::Tree { .. }) => {
                         let left = match *node {
                             Node::Tree { ref left, .. } => left,
                             _ => unreachable!(),
@@ -58,7 +58,7 @@ fn precalc_bitstreams(freqs: &[usize; 256]) -> Result<Vec<Option<Bitstream>>,()>
     };
 }
 
-fn build_freqs(data: &[u8]) -> Box<[usize; 256]> {
+pub fn build_freqs(data: &[u8]) -> Box<[usize; 256]> {
     let mut freqs = Box::new([0; 256]);
 
     for &c in data {
@@ -68,12 +68,194 @@ fn build_freqs(data: &[u8]) -> Box<[usize; 256]> {
     freqs
 }
 
-pub fn encode(data: &[u8]) -> Result<HuffmanData,()> {
-    let freqs = build_freqs(data);
-    let streams = precalc_bitstreams(&freqs).unwrap();
-    let bs = data.iter().
-        map(|c| streams[*c as usize].as_ref().unwrap()).
-        fold(Bitstream::new(), 
-             |mut acc, x| { acc.append_bitstream(x); acc });
-    Ok(HuffmanData { freqs, bs })
+// Halves the frequencies until they fit in an inline table, keeping every used symbol nonzero.
+pub(super) fn scale_freqs(freqs: &mut [usize; 256]) {
+    while freqs.iter().any(|&f| f > MAX_FREQ) {
+        for freq in freqs.iter_mut() {
+            if *freq > 0 {
+                *freq = (*freq / 2).max(1);
+            };
+        };
+    };
+}
+
+// Size of `data` in bits when coded with `book`.
+fn coded_bits(book: &CodeBook, counts: &[usize; 256]) -> usize {
+    (0..256).map(|i| counts[i] * book.length(i as u8)).sum()
+}
+
+pub fn encode(data: &[u8]) -> Result<HuffmanData, String> {
+    let mut freqs = build_freqs(data);
+    scale_freqs(&mut freqs);
+    let bs = CodeBook::from_freqs(&freqs).encode(data)?;
+    Ok(HuffmanData { kind: TableKind::Inline, freqs, bs })
+}
+
+/// Encodes a sequence of blocks.  Each block uses whichever is smallest of the previous block's
+/// table, a delta against it, or a table of its own.  The blocks must be read back in order with
+/// a single `TableSet`, and can't be mixed with blocks from anywhere else.
+#[derive(Default)]
+pub struct Encoder {
+    previous: Option<Box<[usize; 256]>>,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder { previous: None }
+    }
+
+    pub fn encode(&mut self, data: &[u8]) -> Result<HuffmanData, String> {
+        let counts = build_freqs(data);
+        let mut freqs = counts.clone();
+        scale_freqs(&mut freqs);
+
+        let book = CodeBook::from_freqs(&freqs);
+
+        let (kind, freqs, book) = match self.previous.take() {
+            None => (TableKind::Inline, freqs, book),
+            Some(previous) => {
+                let previous_book = CodeBook::from_freqs(&previous);
+                let new_bits = coded_bits(&book, &counts);
+
+                let reuse_cost = coded_bits(&previous_book, &counts);
+                let delta_cost = 8 * delta_bytes(&previous, &freqs).len() + new_bits;
+                let inline_cost = 8 * 512 + new_bits;
+
+                if reuse_cost <= delta_cost && reuse_cost <= inline_cost {
+                    (TableKind::Previous, previous, previous_book)
+                } else if delta_cost <= inline_cost {
+                    (TableKind::Delta(previous), freqs, book)
+                } else {
+                    (TableKind::Inline, freqs, book)
+                }
+            },
+        };
+
+        let bs = book.encode(data)?;
+        self.previous = Some(freqs.clone());
+
+        Ok(HuffmanData { kind, freqs, bs })
+    }
+
+    /// Like `encode_static`, but the table becomes the previous one for the next block, as it
+    /// does for the decoder.  A table with frequencies too large for a delta to reach isn't
+    /// built on, and the next block gets a table of its own.
+    pub fn encode_static(&mut self, data: &[u8], table: &StaticTable)
+                         -> Result<HuffmanData, String> {
+        let hd = encode_static(data, table)?;
+        self.previous = if hd.freqs.iter().all(|&f| f <= MAX_FREQ) {
+            Some(hd.freqs.clone())
+        } else {
+            None
+        };
+
+        Ok(hd)
+    }
+}
+
+/// Encodes with a pre-shared table, so only the table's id is written with the block.
+pub fn encode_static(data: &[u8], table: &StaticTable) -> Result<HuffmanData, String> {
+    let bs = CodeBook::from_freqs(&table.freqs).encode(data)?;
+    Ok(HuffmanData { kind: TableKind::Static(table.id), freqs: table.freqs.clone(), bs })
+}
+
+#[cfg(test)]
+mod test {
+    use huffman;
+    use huffman::{Encoder, HuffmanData, StaticTable, TableKind, TableSet};
+
+    fn round_trip(blocks: &[&[u8]]) -> Vec<TableKind> {
+        let mut encoder = Encoder::new();
+        let mut buf = Vec::new();
+
+        for block in blocks {
+            encoder.encode(block).unwrap().write(&mut buf).unwrap();
+        };
+
+        let mut tables = TableSet::new();
+        let mut reader = &buf[..];
+        let mut kinds = Vec::new();
+
+        for block in blocks {
+            let hd = HuffmanData::read_next(&mut reader, &mut tables).unwrap().unwrap();
+            assert_eq!(huffman::decode(&hd).unwrap(), *block);
+            kinds.push(hd.kind);
+        };
+        assert!(HuffmanData::read_next(&mut reader, &mut tables).unwrap().is_none());
+
+        kinds
+    }
+
+    #[test]
+    fn table_reuse() {
+        let text = b"the quick brown fox jumps over the lazy dog";
+        let similar = b"the quick brown fox jumps over the lazy dog!";
+        let other = (0..=255).cycle().take(4096).collect::<Vec<u8>>();
+
+        let kinds = round_trip(&[text, text, similar, &other]);
+
+        assert_eq!(kinds[0], TableKind::Inline);
+        assert_eq!(kinds[1], TableKind::Previous);
+        match kinds[2] {
+            TableKind::Delta(_) | TableKind::Previous => (),
+            ref kind => panic!("Expected reuse, got {:?}", kind),
+        };
+        // Even a new table is usually cheaper to send as a delta.
+        assert!(kinds[3] != TableKind::Previous);
+    }
+
+    #[test]
+    fn static_then_delta() {
+        let table = StaticTable::train(3, vec![&b"static tables are shared ahead of time"[..]]);
+        let blocks: [&[u8]; 3] = [&[0; 300], b"static tables are shared", b"static tables"];
+
+        let mut encoder = Encoder::new();
+        let mut buf = Vec::new();
+        encoder.encode(blocks[0]).unwrap().write(&mut buf).unwrap();
+        encoder.encode_static(blocks[1], &table).unwrap().write(&mut buf).unwrap();
+        let hd = encoder.encode(blocks[2]).unwrap();
+        assert!(hd.kind != TableKind::Inline);
+        hd.write(&mut buf).unwrap();
+
+        // The last block builds on the static table, not on the first block's.
+        let mut tables = TableSet::new();
+        tables.add(table);
+        let mut reader = &buf[..];
+        for block in blocks.iter() {
+            let hd = HuffmanData::read_next(&mut reader, &mut tables).unwrap().unwrap();
+            assert_eq!(huffman::decode(&hd).unwrap(), *block);
+        };
+    }
+
+    #[test]
+    fn static_too_large_for_delta() {
+        let mut freqs = Box::new([1; 256]);
+        freqs[b'a' as usize] = 1 << 20;
+        let table = StaticTable { id: 4, freqs };
+        let blocks: [&[u8]; 2] = [b"aaaab", b"aaaabbbbcccc"];
+
+        let mut encoder = Encoder::new();
+        let mut buf = Vec::new();
+        encoder.encode_static(blocks[0], &table).unwrap().write(&mut buf).unwrap();
+        let hd = encoder.encode(blocks[1]).unwrap();
+        assert_eq!(hd.kind, TableKind::Inline);
+        hd.write(&mut buf).unwrap();
+
+        let mut tables = TableSet::new();
+        tables.add(table);
+        let mut reader = &buf[..];
+        for block in blocks.iter() {
+            let hd = HuffmanData::read_next(&mut reader, &mut tables).unwrap().unwrap();
+            assert_eq!(huffman::decode(&hd).unwrap(), *block);
+        };
+    }
+
+    #[test]
+    fn large_counts() {
+        let mut data = vec![0; 100_000];
+        data.extend_from_slice(b"some other bytes");
+
+        round_trip(&[&data, &data[..70_000]]);
+        assert_eq!(huffman::decode(&huffman::encode(&data).unwrap()).unwrap(), data);
+    }
 }
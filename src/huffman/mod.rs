@@ -1,19 +1,28 @@
+mod adaptive;
+mod codebook;
 mod decode;
 mod encode;
+mod order1;
+mod static_table;
 use bitstream::Bitstream;
 use std::io;
 use std::io::Write;
 use std::io::Read;
+use std::cmp::Ordering;
 use std::rc::Rc;
-use byteorder::{BigEndian, ByteOrder};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, WriteBytesExt};
 
-pub use self::encode::encode;
+pub use self::adaptive::{decode_adaptive, encode_adaptive, AdaptiveDecoder, AdaptiveEncoder};
+pub use self::codebook::CodeBook;
+pub use self::encode::{build_freqs, encode, encode_static, Encoder};
+pub use self::order1::{code_lengths, decode_order1, encode_order1};
+pub use self::static_table::{StaticTable, TableSet};
 pub use self::decode::decode;
 
-#[derive(Debug, PartialEq, PartialOrd)]
+#[derive(Debug, PartialEq)]
 pub enum Node {
     Leaf { freq: usize, val: u8, },
-    Tree { freq: usize, left: Rc<Node>, right: Rc<Node>, },
+    Tree { freq: usize, depth: usize, val: u8, left: Rc<Node>, right: Rc<Node>, },
 }
 
 impl Node {
@@ -22,24 +31,132 @@ impl Node {
             Node::Leaf { freq, .. } | Node::Tree { freq, .. } => freq,
         }
     }
+
+    /// Height of the subtree rooted at this node; leaves have depth 0.
+    pub fn get_depth(&self) -> usize {
+        match *self {
+            Node::Leaf { .. } => 0,
+            Node::Tree { depth, .. } => depth,
+        }
+    }
+
+    /// Smallest symbol contained in the subtree rooted at this node.
+    pub fn get_val(&self) -> u8 {
+        match *self {
+            Node::Leaf { val, .. } | Node::Tree { val, .. } => val,
+        }
+    }
+
+    /// The order in which `build_tree` merges nodes: lower frequency first, then shallower
+    /// subtrees, then the subtree holding the smaller symbol.  No two distinct nodes in one tree
+    /// compare equal, since their symbol sets are disjoint.
+    ///
+    /// This is part of the file format.  Encoder and decoder both rebuild the tree from the
+    /// frequency table, so changing this breaks every existing file.
+    pub fn merge_order(&self, other: &Node) -> Ordering {
+        self.get_freq().cmp(&other.get_freq())
+            .then(self.get_depth().cmp(&other.get_depth()))
+            .then(self.get_val().cmp(&other.get_val()))
+    }
+}
+
+/// Where a block's frequency table comes from.  This is the first byte of each block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TableKind {
+    /// The table is written out in full before the bits.
+    Inline,
+    /// A `StaticTable` with this id, which the decoder must already have.
+    Static(u16),
+    /// The same table as the previous block.
+    Previous,
+    /// Written as the changes from the previous block's table, which is held here.
+    Delta(Box<[usize; 256]>),
+}
+
+const TABLE_INLINE: u8 = 0;
+const TABLE_STATIC: u8 = 1;
+const TABLE_PREVIOUS: u8 = 2;
+const TABLE_DELTA: u8 = 3;
+
+/// Largest frequency an inline table can hold.
+pub const MAX_FREQ: usize = 0xffff;
+
+// A delta is a bitmap of the symbols whose frequency changed, followed by the zigzag-encoded
+// difference for each of them as a base-128 varint.
+fn delta_bytes(base: &[usize; 256], freqs: &[usize; 256]) -> Vec<u8> {
+    let mut bytes = vec![0; 32];
+
+    for i in 0..256 {
+        if base[i] == freqs[i] {
+            continue;
+        };
+
+        bytes[i >> 3] |= 1 << (i & 7);
+
+        let diff = freqs[i] as i64 - base[i] as i64;
+        let mut zigzag = ((diff << 1) ^ (diff >> 63)) as u64;
+        while zigzag >= 0x80 {
+            bytes.push((zigzag as u8) | 0x80);
+            zigzag >>= 7;
+        };
+        bytes.push(zigzag as u8);
+    };
+
+    bytes
+}
+
+fn read_delta(reader: &mut dyn Read, base: &[usize; 256]) -> io::Result<Box<[usize; 256]>> {
+    let mut bitmap = [0; 32];
+    reader.read_exact(&mut bitmap)?;
+
+    let mut freqs = Box::new(*base);
+
+    for (i, freq) in freqs.iter_mut().enumerate() {
+        if bitmap[i >> 3] & (1 << (i & 7)) == 0 {
+            continue;
+        };
+
+        let mut zigzag: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = reader.read_u8()?;
+            zigzag |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            };
+            if shift > 21 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Delta too long"));
+            };
+        };
+
+        let diff = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        let new_freq = *freq as i64 + diff;
+        if new_freq < 0 || new_freq > MAX_FREQ as i64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Delta out of range"));
+        };
+        *freq = new_freq as usize;
+    };
+
+    Ok(freqs)
 }
 
 pub struct HuffmanData {
+    pub kind: TableKind,
     pub freqs: Box<[usize; 256]>,
     pub bs: Bitstream,
 }
 
 impl HuffmanData {
     pub fn byte_len(&self) -> usize {
-        (self.bs.pos.end >> 3) as usize
+        self.bs.pos.end >> 3
     }
 
-    fn write_freqs(&self, writer: &mut Write) -> io::Result<usize> {
+    fn write_freqs(&self, writer: &mut dyn Write) -> io::Result<usize> {
         let mut new_freqs = [0; 256];
 
-        #[cfg_attr(feature = "clippy", allow(needless_range_loop))]
-        for i in 0..256 {
-            new_freqs[i] = self.freqs[i] as u16;
+        for (new_freq, &freq) in new_freqs.iter_mut().zip(self.freqs.iter()) {
+            *new_freq = freq as u16;
         };
 
         let mut bytes = [0; 512];
@@ -51,12 +168,9 @@ impl HuffmanData {
         }
     }
 
-    fn read_freqs(reader: &mut Read) -> io::Result<Option<Box<[usize; 256]>>> {
+    fn read_freqs(reader: &mut dyn Read) -> io::Result<Box<[usize; 256]>> {
         let mut bytes = [0; 512];
-
-        if reader.read(&mut bytes).is_err() {
-            return Ok(None);                // FIXME: errors other than EOF?
-        };
+        reader.read_exact(&mut bytes)?;
 
         let mut freqs_u16 = [0; 256];
         BigEndian::read_u16_into(&bytes, &mut freqs_u16[0..]);
@@ -67,28 +181,85 @@ impl HuffmanData {
             freqs[i] = freqs_u16[i] as usize;
         };
 
-        Ok(Some(freqs))
+        Ok(freqs)
     }
 
-    pub fn write(&self, mut writer: &mut Write) -> io::Result<usize> {
-        let bytes_out = 
-            match self.write_freqs(writer) {
-                Err(err) => return Err(err),
-                Ok(nb) => nb,
-            }
-            +
-            match self.bs.write(&mut writer) {
-                Err(err) => return Err(err),
-                Ok(nb) => nb,
-            };
-        Ok(bytes_out)
+    pub fn write(&self, mut writer: &mut dyn Write) -> io::Result<usize> {
+        let table_bytes = match self.kind {
+            TableKind::Inline => {
+                writer.write_u8(TABLE_INLINE)?;
+                1 + self.write_freqs(writer)?
+            },
+            TableKind::Static(id) => {
+                writer.write_u8(TABLE_STATIC)?;
+                writer.write_u16::<BigEndian>(id)?;
+                3
+            },
+            TableKind::Previous => {
+                writer.write_u8(TABLE_PREVIOUS)?;
+                1
+            },
+            TableKind::Delta(ref base) => {
+                let delta = delta_bytes(base, &self.freqs);
+                writer.write_u8(TABLE_DELTA)?;
+                writer.write_all(&delta)?;
+                1 + delta.len()
+            },
+        };
+
+        Ok(table_bytes + self.bs.write(&mut writer)?)
     }
 
-    pub fn read(mut reader: &mut Read) -> io::Result<Option<HuffmanData>> {
-        let freqs = match HuffmanData::read_freqs(&mut reader) {
-            Err(err) => panic!("read_freqs error: {:?}", err),
-            Ok(Some(freqs)) => freqs,
-            Ok(None) => return Ok(None),
+    /// Reads a block that carries its own table.
+    pub fn read(reader: &mut dyn Read) -> io::Result<Option<HuffmanData>> {
+        HuffmanData::read_with(reader, &TableSet::new())
+    }
+
+    /// Reads the next block of a sequence written by one `Encoder`, keeping its table in `tables`
+    /// as the previous one for the block after.
+    pub fn read_next(reader: &mut dyn Read, tables: &mut TableSet)
+                     -> io::Result<Option<HuffmanData>> {
+        let hd = HuffmanData::read_with(reader, tables)?;
+        if let Some(ref hd) = hd {
+            tables.set_previous(hd.freqs.clone());
+        };
+        Ok(hd)
+    }
+
+    /// Reads a block, looking up static tables in `tables`.  Blocks that refer to the previous
+    /// block's table take it from `tables` too; see `read_next`.
+    pub fn read_with(mut reader: &mut dyn Read, tables: &TableSet) -> io::Result<Option<HuffmanData>> {
+        let tag = match reader.read_u8() {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(tag) => tag,
+        };
+
+        let (kind, freqs) = match tag {
+            TABLE_INLINE => (TableKind::Inline, HuffmanData::read_freqs(&mut reader)?),
+            TABLE_STATIC => {
+                let id = reader.read_u16::<BigEndian>()?;
+                match tables.get(id) {
+                    Some(table) => (TableKind::Static(id), table.freqs.clone()),
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                      format!("Unknown static table {}", id))),
+                }
+            },
+            TABLE_PREVIOUS | TABLE_DELTA => {
+                let base = match tables.previous() {
+                    Some(base) => Box::new(*base),
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                                      "No previous table")),
+                };
+
+                if tag == TABLE_PREVIOUS {
+                    (TableKind::Previous, base)
+                } else {
+                    let freqs = read_delta(reader, &base)?;
+                    (TableKind::Delta(base), freqs)
+                }
+            },
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Bad table kind")),
         };
 
         let bs = match Bitstream::read(reader) {
@@ -97,19 +268,20 @@ impl HuffmanData {
             Ok(None) => return Ok(None),
         };
 
-        Ok(Some(HuffmanData { freqs, bs }))
+        Ok(Some(HuffmanData { kind, freqs, bs }))
     }
 }
 
-// input is ordered
+// Every symbol gets a leaf, including those with a frequency of zero, so the tree is the same shape
+// no matter which symbols a block uses.  The two lowest nodes by `Node::merge_order` are merged
+// at each step; the lower one goes on the left (bit 0).
 fn build_tree(vals: &[usize; 256]) -> Rc<Node> {
-    // this produces an improper tree: values always end up being 8 bits
     let mut nodes: Vec<_> = (0..256).
         map(|i| (i as u8, vals[i])).
         map(|val| Rc::new(Node::Leaf { val: val.0, freq: val.1 })).
         collect();
 
-    nodes.sort_unstable_by(|n1, n2| n1.partial_cmp(n2).unwrap().reverse());
+    nodes.sort_unstable_by(|n1, n2| n1.merge_order(n2).reverse());
 
     // need to go down from root, find a spot for our node, then insert it
     loop {
@@ -120,13 +292,55 @@ fn build_tree(vals: &[usize; 256]) -> Rc<Node> {
             (Some(left), None) => return left,
             (Some(left), Some(right)) => {
                 let freq = left.get_freq() + right.get_freq();
-                let node = Rc::new(Node::Tree { left, right, freq });
+                let depth = 1 + left.get_depth().max(right.get_depth());
+                let val = left.get_val().min(right.get_val());
+                let node = Rc::new(Node::Tree { left, right, freq, depth, val });
 
                 nodes.push(node);
 
-                nodes.sort_unstable_by(|n1, n2| n1.partial_cmp(n2).unwrap().reverse());
+                nodes.sort_unstable_by(|n1, n2| n1.merge_order(n2).reverse());
             },
             _ => panic!("Must have nodes to build_tree"),
         };
     };
 }
+
+#[cfg(test)]
+mod test {
+    use huffman;
+
+    fn write_bits(data: &[u8]) -> Vec<u8> {
+        let hd = huffman::encode(data).unwrap();
+        let mut out = Vec::new();
+        hd.write(&mut out).unwrap();
+
+        assert_eq!(huffman::decode(&hd).unwrap(), data);
+
+        // Skip the table kind and frequency table; it's a plain count of each byte.
+        assert_eq!(out[0], 0);
+        out.split_off(513)
+    }
+
+    // These lock the exact output of the encoder.  If one of them changes, old files can no
+    // longer be decoded.
+    #[test]
+    fn golden_vectors() {
+        assert_eq!(write_bits(b""), [0, 0, 0, 0]);
+        assert_eq!(write_bits(b"a"), [0, 0, 0, 1, 1]);
+        assert_eq!(write_bits(b"abracadabra"), [0, 0, 0, 24, 118, 45, 118]);
+        assert_eq!(write_bits(b"this is a test."), [0, 0, 0, 44, 78, 120, 120, 221, 198, 13]);
+    }
+
+    #[test]
+    fn golden_uniform() {
+        // With every frequency equal, ties fall through to the symbol order and each code is the
+        // symbol's own bits, most significant first.
+        let data = (0..256).map(|i| i as u8).collect::<Vec<u8>>();
+        let bits = write_bits(&data);
+
+        assert_eq!(&bits[0..4], &[0, 0, 8, 0]);
+        for (i, &byte) in bits[4..].iter().enumerate() {
+            assert_eq!(byte, (i as u8).reverse_bits());
+        };
+    }
+}
@@ -0,0 +1,252 @@
+// Order-1 Huffman coding: each byte is coded with a table chosen by the byte before it.  Giving
+// each of the 256 contexts its own table would make the header far too big, so contexts with
+// similar statistics are clustered to share one of at most `MAX_TABLES` tables.
+//
+// Layout: number of tables (u8), the table for each context packed two to a byte, each table's
+// canonical code lengths (a bitmap of used symbols, then one byte per used symbol), then the
+// bits as a `Bitstream`.
+use bitstream::Bitstream;
+use huffman::CodeBook;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub const MAX_TABLES: usize = 8;
+
+// Rough cost of a symbol that a table has no code for, used while clustering.
+const MISSING_COST: usize = 24;
+
+/// Huffman code lengths for the symbols with a nonzero count.  Unlike `build_tree`, unused
+/// symbols get no code at all.
+pub fn code_lengths(counts: &[usize; 256]) -> [u8; 256] {
+    let mut lengths = [0; 256];
+    let used = (0..256).filter(|&i| counts[i] > 0).collect::<Vec<usize>>();
+
+    if used.len() == 1 {
+        lengths[used[0]] = 1;
+        return lengths;
+    };
+
+    // Nodes 0..256 are the symbols; merged nodes are numbered from 256 up.  Ties go to the lower
+    // number, so the lengths don't depend on the heap's implementation.
+    let mut parents = vec![0; 512];
+    let mut heap = used.iter().map(|&i| Reverse((counts[i], i))).collect::<BinaryHeap<_>>();
+    let mut next = 256;
+
+    while heap.len() > 1 {
+        let Reverse((freq_a, a)) = heap.pop().unwrap();
+        let Reverse((freq_b, b)) = heap.pop().unwrap();
+
+        parents[a] = next;
+        parents[b] = next;
+        heap.push(Reverse((freq_a + freq_b, next)));
+        next += 1;
+    };
+
+    let root = next - 1;
+    for &sym in &used {
+        let mut node = sym;
+        while node != root {
+            node = parents[node];
+            lengths[sym] += 1;
+        };
+    };
+
+    lengths
+}
+
+fn cost(counts: &[usize; 256], lengths: &[u8; 256]) -> usize {
+    (0..256).
+        filter(|&i| counts[i] > 0).
+        map(|i| counts[i] * if lengths[i] == 0 { MISSING_COST } else { lengths[i] as usize }).
+        sum()
+}
+
+fn header_len(lengths: &[u8; 256]) -> usize {
+    32 + lengths.iter().filter(|&&l| l > 0).count()
+}
+
+// Splits the used contexts into at most `k` clusters, k-means style: assign every context to the
+// table that codes it cheapest, rebuild the tables from their contexts, and repeat.  Returns the
+// table for each context and each table's code lengths.
+fn cluster(counts: &[[usize; 256]], k: usize) -> (Vec<u8>, Vec<[u8; 256]>) {
+    let mut by_size = (0..256).filter(|&c| counts[c].iter().any(|&n| n > 0)).collect::<Vec<_>>();
+    by_size.sort_by_key(|&c| Reverse((counts[c].iter().sum::<usize>(), Reverse(c))));
+
+    let mut tables = by_size.iter().take(k).map(|&c| code_lengths(&counts[c])).collect::<Vec<_>>();
+    let mut map = vec![0; 256];
+
+    for _ in 0..6 {
+        for &c in &by_size {
+            map[c] = (0..tables.len()).min_by_key(|&t| (cost(&counts[c], &tables[t]), t)).unwrap();
+        };
+
+        let mut sums = vec![[0; 256]; tables.len()];
+        for &c in &by_size {
+            for (sum, &n) in sums[map[c]].iter_mut().zip(counts[c].iter()) {
+                *sum += n;
+            };
+        };
+
+        // Drop tables nothing was assigned to, and renumber.
+        let mut renumber = vec![0; tables.len()];
+        tables.clear();
+        for (t, sum) in sums.iter().enumerate() {
+            renumber[t] = tables.len();
+            if sum.iter().any(|&n| n > 0) {
+                tables.push(code_lengths(sum));
+            };
+        };
+        for &c in &by_size {
+            map[c] = renumber[map[c]];
+        };
+    };
+
+    if tables.is_empty() {
+        tables.push([0; 256]);
+    };
+
+    (map.iter().map(|&t| t as u8).collect(), tables)
+}
+
+pub fn encode_order1(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut counts = vec![[0; 256]; 256];
+    let mut prev = 0;
+    for &c in data {
+        counts[prev as usize][c as usize] += 1;
+        prev = c;
+    };
+
+    // More tables fit the data better but cost more to send; try a few and keep the smallest.
+    let (map, tables) = [1, 2, 4, MAX_TABLES].iter().
+        map(|&k| cluster(&counts, k)).
+        min_by_key(|(map, tables)| {
+            let bits: usize = (0..256).map(|c| cost(&counts[c], &tables[map[c] as usize])).sum();
+            bits / 8 + tables.iter().map(header_len).sum::<usize>()
+        }).
+        unwrap();
+
+    let books = tables.iter().
+        map(CodeBook::from_lengths).
+        collect::<Result<Vec<_>, _>>()?;
+
+    let mut buf = vec![tables.len() as u8];
+    for pair in map.chunks(2) {
+        buf.push(pair[0] << 4 | pair[1]);
+    };
+    for lengths in &tables {
+        let mut bitmap = [0; 32];
+        for i in (0..256).filter(|&i| lengths[i] > 0) {
+            bitmap[i >> 3] |= 1 << (i & 7);
+        };
+        buf.extend_from_slice(&bitmap);
+        buf.extend(lengths.iter().filter(|&&l| l > 0));
+    };
+
+    let mut bs = Bitstream::new();
+    let mut prev = 0;
+    for &c in data {
+        match books[map[prev as usize] as usize].code(c) {
+            Some(code) => bs.append_bitstream(code),
+            None => return Err(format!("No code for symbol {}", c)),
+        };
+        prev = c;
+    };
+
+    bs.write(&mut buf).map_err(|err| err.to_string())?;
+
+    Ok(buf)
+}
+
+pub fn decode_order1(buf: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || String::from("Order-1 block is truncated");
+
+    let num_tables = *buf.first().ok_or_else(truncated)? as usize;
+    if num_tables == 0 || num_tables > MAX_TABLES {
+        return Err(String::from("Bad number of order-1 tables"));
+    };
+
+    let packed = buf.get(1..129).ok_or_else(truncated)?;
+    let map = packed.iter().flat_map(|&b| vec![b >> 4, b & 0x0f]).collect::<Vec<u8>>();
+    if map.iter().any(|&t| t as usize >= num_tables) {
+        return Err(String::from("Bad order-1 context map"));
+    };
+
+    let mut pos = 129;
+    let mut tries = Vec::with_capacity(num_tables);
+    for _ in 0..num_tables {
+        let bitmap = buf.get(pos..pos + 32).ok_or_else(truncated)?;
+        pos += 32;
+
+        let mut lengths = [0; 256];
+        for (i, length) in lengths.iter_mut().enumerate() {
+            if bitmap[i >> 3] & (1 << (i & 7)) != 0 {
+                *length = *buf.get(pos).ok_or_else(truncated)?;
+                pos += 1;
+            };
+        };
+
+        tries.push(CodeBook::from_lengths(&lengths)?.build_trie()?);
+    };
+
+    let mut bs = match Bitstream::read(&mut &buf[pos..]) {
+        Ok(Some(bs)) => bs,
+        _ => return Err(truncated()),
+    };
+
+    let mut out = Vec::new();
+    let mut prev = 0;
+    while let Some(c) = CodeBook::decode_symbol(&tries[map[prev as usize] as usize], &mut bs)? {
+        out.push(c);
+        prev = c;
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use huffman;
+    use huffman::{code_lengths, decode_order1, encode_order1};
+
+    #[test]
+    fn lengths() {
+        let mut counts = [0; 256];
+        counts[b'a' as usize] = 5;
+        counts[b'b' as usize] = 2;
+        counts[b'c' as usize] = 1;
+        counts[b'd' as usize] = 1;
+
+        let lengths = code_lengths(&counts);
+
+        assert_eq!(&lengths[b'a' as usize..b'e' as usize], &[1, 2, 3, 3]);
+        assert_eq!(lengths.iter().filter(|&&l| l > 0).count(), 4);
+
+        let mut single = [0; 256];
+        single[7] = 100;
+        assert_eq!(code_lengths(&single)[7], 1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 5] = [b"", b"a", b"aaaa", b"abracadabra", b"this is a test."];
+
+        for &input in inputs.iter() {
+            assert_eq!(decode_order1(&encode_order1(input).unwrap()).unwrap(), input);
+        };
+
+        let all = (0..=255).chain((0..=255).rev()).cycle().take(5000).collect::<Vec<u8>>();
+        assert_eq!(decode_order1(&encode_order1(&all).unwrap()).unwrap(), all);
+    }
+
+    #[test]
+    fn beats_order_0() {
+        let data = include_bytes!("order1.rs");
+
+        let mut order0 = Vec::new();
+        huffman::encode(data).unwrap().write(&mut order0).unwrap();
+        let order1 = encode_order1(data).unwrap();
+
+        assert!(order1.len() < order0.len());
+        assert_eq!(&decode_order1(&order1).unwrap()[..], &data[..]);
+    }
+}
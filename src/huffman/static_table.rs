@@ -0,0 +1,166 @@
+use huffman::build_freqs;
+use huffman::encode::scale_freqs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::io::Read;
+use std::path::Path;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// A frequency table that encoder and decoder agree on ahead of time.  Blocks coded with it carry
+/// only the table's id, which makes Huffman coding worthwhile for messages too small to pay for
+/// a table of their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticTable {
+    pub id: u16,
+    pub freqs: Box<[usize; 256]>,
+}
+
+impl StaticTable {
+    /// Builds a table from the combined frequencies of some sample data.  Every symbol is counted
+    /// once more than it appears, so bytes missing from the samples still get reasonable codes.
+    pub fn train<'a, I>(id: u16, samples: I) -> StaticTable
+        where I: IntoIterator<Item = &'a [u8]> {
+        let mut freqs = Box::new([1; 256]);
+
+        for sample in samples {
+            let sample_freqs = build_freqs(sample);
+            for (freq, &count) in freqs.iter_mut().zip(sample_freqs.iter()) {
+                *freq += count;
+            };
+        };
+
+        // Scaled like any other table, so it can be the base of a delta.
+        scale_freqs(&mut freqs);
+
+        StaticTable { id, freqs }
+    }
+
+    /// `train` over the contents of some example files.
+    pub fn train_files(id: u16, paths: &[&Path]) -> io::Result<StaticTable> {
+        let mut samples = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let mut bytes = Vec::new();
+            File::open(path)?.read_to_end(&mut bytes)?;
+            samples.push(bytes);
+        };
+
+        Ok(StaticTable::train(id, samples.iter().map(|s| &s[..])))
+    }
+
+    pub fn write(&self, writer: &mut dyn Write) -> io::Result<usize> {
+        writer.write_u16::<BigEndian>(self.id)?;
+
+        for &freq in self.freqs.iter() {
+            writer.write_u32::<BigEndian>(freq as u32)?;
+        };
+
+        Ok(2 + 4 * 256)
+    }
+
+    pub fn read(reader: &mut dyn Read) -> io::Result<StaticTable> {
+        let id = reader.read_u16::<BigEndian>()?;
+        let mut freqs = Box::new([0; 256]);
+
+        for freq in freqs.iter_mut() {
+            *freq = reader.read_u32::<BigEndian>()? as usize;
+        };
+
+        Ok(StaticTable { id, freqs })
+    }
+}
+
+/// The tables a decoder knows about: the static ones, and the one used by the last block read.
+#[derive(Clone, Debug, Default)]
+pub struct TableSet {
+    statics: Vec<StaticTable>,
+    previous: Option<Box<[usize; 256]>>,
+}
+
+impl TableSet {
+    pub fn new() -> TableSet {
+        TableSet { statics: Vec::new(), previous: None }
+    }
+
+    pub fn previous(&self) -> Option<&[usize; 256]> {
+        self.previous.as_deref()
+    }
+
+    pub fn set_previous(&mut self, freqs: Box<[usize; 256]>) {
+        self.previous = Some(freqs);
+    }
+
+    /// Adds a table, replacing any existing one with the same id.
+    pub fn add(&mut self, table: StaticTable) {
+        self.statics.retain(|t| t.id != table.id);
+        self.statics.push(table);
+    }
+
+    pub fn get(&self, id: u16) -> Option<&StaticTable> {
+        self.statics.iter().find(|t| t.id == id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use huffman;
+    use huffman::{HuffmanData, StaticTable, TableSet, MAX_FREQ};
+
+    const SAMPLES: [&str; 3] = [
+        "{\"method\": \"get_user\", \"params\": {\"id\": 1234}}",
+        "{\"method\": \"set_name\", \"params\": {\"id\": 42, \"name\": \"bob\"}}",
+        "{\"method\": \"list_users\", \"params\": {\"offset\": 0, \"limit\": 100}}",
+    ];
+
+    fn table() -> StaticTable {
+        StaticTable::train(7, SAMPLES.iter().map(|s| s.as_bytes()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = b"{\"method\": \"get_user\", \"params\": {\"id\": 99}}";
+        let table = table();
+
+        let hd = huffman::encode_static(message, &table).unwrap();
+        let mut buf = Vec::new();
+        hd.write(&mut buf).unwrap();
+
+        assert!(buf.len() < message.len());
+
+        let mut tables = TableSet::new();
+        tables.add(table);
+
+        let read = HuffmanData::read_with(&mut &buf[..], &tables).unwrap().unwrap();
+        assert_eq!(huffman::decode(&read).unwrap(), &message[..]);
+
+        assert!(HuffmanData::read(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn unseen_symbols() {
+        let table = table();
+        let hd = huffman::encode_static(b"\x00\xff~", &table).unwrap();
+
+        assert_eq!(huffman::decode(&hd).unwrap(), b"\x00\xff~");
+    }
+
+    #[test]
+    fn large_samples() {
+        let sample = b"aaaaaaab".repeat(100_000);
+        let table = StaticTable::train(1, vec![&sample[..]]);
+
+        assert!(table.freqs.iter().all(|&f| f > 0 && f <= MAX_FREQ));
+        assert!(table.freqs[b'a' as usize] > table.freqs[b'b' as usize]);
+    }
+
+    #[test]
+    fn write_read() {
+        let table = table();
+        let mut buf = Vec::new();
+        let len = table.write(&mut buf).unwrap();
+
+        assert_eq!(len, buf.len());
+        assert_eq!(StaticTable::read(&mut &buf[..]).unwrap(), table);
+    }
+}
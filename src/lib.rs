@@ -0,0 +1,21 @@
+#![cfg_attr(feature="clippy", feature(plugin))]
+#![cfg_attr(feature="clippy", plugin(clippy))]
+
+extern crate byteorder;
+
+pub mod bitstream;
+pub mod huffman;
+pub mod rle;
+pub mod bwt;
+pub mod cm;
+pub mod distance;
+pub mod entropy;
+pub mod grep;
+pub mod mtf;
+pub mod pipeline;
+pub mod range;
+pub mod rans;
+pub mod unicode;
+
+#[cfg(test)]
+mod testdata;
@@ -1,26 +1,27 @@
 #![cfg_attr(feature="clippy", feature(plugin))]
 #![cfg_attr(feature="clippy", plugin(clippy))]
 
-extern crate byteorder;
+extern crate huffman_rust;
 extern crate time;
 
-mod bitstream;
-mod huffman;
-mod rle;
-mod bwt;
-mod mtf;
-use std::error::Error;
+use huffman_rust::bwt::DecodeMode;
+use huffman_rust::pipeline::{Compressor, Decompressor, Options};
+use std::env;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
 use std::io::prelude::*;
 use std::path::Path;
+use std::process;
+use std::thread;
 
 fn open_file(path: &Path) -> File {
     let display = path.display();
 
-    match File::open(&path) {
+    match File::open(path) {
         Err(why) => panic!("couldn't open {}: {}", display,
-                                                   why.description()),
+                                                   why),
         Ok(file) => file,
     }
 }
@@ -38,66 +39,74 @@ fn read_file(path: &Path) -> Vec<u8> {
 fn create_file(path: &Path) -> File {
     let display = path.display();
 
-    match OpenOptions::new().write(true).create(true).truncate(true).open(&path) {
+    match OpenOptions::new().write(true).create(true).truncate(true).open(path) {
         Err(why) => panic!("couldn't open {}: {}", display,
-                                                   why.description()),
+                                                   why),
         Ok(file) => file,
     }
 }
 
-fn encode(mut write_file: &File, data: &[u8]) {
-    for chunk in data.chunks(900_000) {
-        let chunk_start = time::now();
-
-        let start = time::now();
-        let bwted = bwt::encode(chunk);
-        println!("bwt chunk in {}", time::now() - start);
+fn threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
 
-        let start = time::now();
-        let mtfed = mtf::encode(&bwted);
-        println!("mtf chunk in {}", time::now() - start);
+fn encode(mut write_file: &File, data: &[u8]) {
+    // Entry points let the decoder undo each block on every thread.
+    let options = Options { entry_points: threads() - 1, ..Options::default() };
+    let mut compressor = Compressor::new(options);
 
+    for block in options.blocks(data) {
         let start = time::now();
-        let rled = rle::encode(&mtfed);
-        println!("rle chunk in {}", time::now() - start);
-        
-        let start = time::now();
-        let huffed = match huffman::encode(&rled) {
-            Ok(huffed) => huffed,
-            Err(()) => panic!("Error encoding"),
-        };
-        println!("huff chunk in {}", time::now() - start);
 
-        match huffed.write(&mut write_file) {
-            Ok(_) => (),
-            _ => panic!("Couldn't write file"),
+        let nbytes = match compressor.write_block(block, &mut write_file) {
+            Ok(nbytes) => nbytes,
+            Err(err) => panic!("Couldn't write file: {}", err),
         };
 
-        println!("encoded chunk in {}", time::now() - chunk_start);
+        println!("encoded chunk in {} ({} bytes)", time::now() - start, nbytes);
     };
 }
 
 fn decode(mut read_file: &File) -> Vec<u8> {
     let mut bytes = Vec::new();
+    let mut decompressor = Decompressor::with_threads(DecodeMode::Fast, threads());
 
     loop {
-        let hd = match huffman::HuffmanData::read(&mut read_file) {
-            Ok(Some(hd)) => hd,
+        let block = match decompressor.read_block(&mut read_file) {
+            Ok(Some(block)) => block,
             Ok(None) => return bytes,
             Err(err) => panic!("Couldn't read file: {:?}", err),
         };
 
-        let unhuffed = huffman::decode(&hd).unwrap();
-        let unrled = rle::decode(&unhuffed);
-        let unmtfed = mtf::decode(&unrled);
-        let unbwted = bwt::decode(&unmtfed);
-
         // FIXME: would this take a lot of time?
-        bytes.extend_from_slice(&unbwted);
+        bytes.extend_from_slice(&block);
+    };
+}
+
+// Prints the lines of a compressed file that contain a pattern.
+fn grep(pattern: &str, path: &Path) {
+    let mut reader = BufReader::new(open_file(path));
+    let stdout = io::stdout();
+
+    match huffman_rust::grep::grep(pattern.as_bytes(), &mut reader, &mut stdout.lock()) {
+        Ok(0) => process::exit(1),
+        Ok(_) => (),
+        Err(err) => panic!("Couldn't search {}: {}", path.display(), err),
     };
 }
 
 fn main() {
+    let args = env::args().collect::<Vec<String>>();
+
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("grep") if args.len() == 4 => return grep(&args[2], Path::new(&args[3])),
+        Some(_) => {
+            eprintln!("usage: {} [grep PATTERN FILE]", args[0]);
+            process::exit(2);
+        },
+        None => (),
+    };
+
     let data = read_file(Path::new("../excspeed.tar"));
     let outpath = Path::new("../excspeed.tar.zzz");
 
@@ -1,38 +1,324 @@
-pub fn encode(data: &[u8]) -> Vec<u8> {
-    let mut vals = (0..256 as usize).map(|i| i as u8).collect::<Vec<u8>>();
+// Move-to-front coding: each byte is replaced by its position in a list of all byte values, and
+// then moved to the front of the list.  After the BWT most positions are small, so the list is
+// kept in a fixed array and only the part in front of the byte is shifted along, which costs
+// about as much as finding it.
+//
+// `ListUpdate` has variations on it that reorder the list more cautiously, which can leave
+// the ranks a little cheaper to code.
+use std::mem;
 
-    let mut out = Vec::with_capacity(data.len());
+// Finds `c` in `vals` and moves it to the front, returning where it was.  The list is shifted
+// along while looking, so it's only gone through once.
+fn move_to_front<T: Copy + PartialEq>(vals: &mut [T], c: T) -> usize {
+    let mut idx = 0;
+    let mut prev = c;
+    while vals[idx] != c {
+        mem::swap(&mut vals[idx], &mut prev);
+        idx += 1;
+    };
+    vals[idx] = prev;
 
-    for &c in data {
-        let idx = vals.iter().position(|&v| v == c).unwrap();
+    idx
+}
 
-        vals.remove(idx);
-        vals.insert(0, c);
+// Moves the value at `idx` to the front, returning it.
+fn take_to_front<T: Copy>(vals: &mut [T], idx: usize) -> T {
+    let c = vals[idx];
+
+    vals.copy_within(0..idx, 1);
+    vals[0] = c;
+
+    c
+}
 
-        out.push(idx as u8);;
+fn identity() -> [u8; 256] {
+    let mut vals = [0u8; 256];
+    for (i, val) in vals.iter_mut().enumerate() {
+        *val = i as u8;
     };
+    vals
+}
+
+/// `encode` for input that comes in pieces: the list is kept from one `push` to the next.
+#[derive(Clone)]
+pub struct MtfEncoder {
+    vals: [u8; 256],
+}
+
+impl Default for MtfEncoder {
+    fn default() -> MtfEncoder {
+        MtfEncoder::new()
+    }
+}
+
+impl MtfEncoder {
+    pub fn new() -> MtfEncoder {
+        MtfEncoder { vals: identity() }
+    }
+
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let vals = &mut self.vals;
+
+        out.extend(data.iter().map(|&c| move_to_front(vals, c) as u8));
+    }
+
+    /// Ends the input.  Each byte is coded as it comes, so there's nothing left to write.
+    pub fn finish(self, _out: &mut Vec<u8>) {}
+}
+
+/// The inverse of `MtfEncoder`.
+#[derive(Clone)]
+pub struct MtfDecoder {
+    vals: [u8; 256],
+}
+
+impl Default for MtfDecoder {
+    fn default() -> MtfDecoder {
+        MtfDecoder::new()
+    }
+}
+
+impl MtfDecoder {
+    pub fn new() -> MtfDecoder {
+        MtfDecoder { vals: identity() }
+    }
+
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let vals = &mut self.vals;
 
+        out.extend(data.iter().map(|&i| take_to_front(vals, i as usize)));
+    }
+
+    /// Ends the input.  Every rank stands for one byte, so it can't end in the wrong place.
+    pub fn finish(self, _out: &mut Vec<u8>) {}
+}
+
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut encoder = MtfEncoder::new();
+    encoder.push(data, &mut out);
+    encoder.finish(&mut out);
     out
 }
 
 pub fn decode(data: &[u8]) -> Vec<u8> {
-    let mut vals = (0..256 as usize).map(|i| i as u8).collect::<Vec<u8>>();
-
     let mut out = Vec::with_capacity(data.len());
+    let mut decoder = MtfDecoder::new();
+    decoder.push(data, &mut out);
+    decoder.finish(&mut out);
+    out
+}
 
-    for &i in data {
-        out.push(vals[i as usize]);
+/// Like `encode`, for symbols below `alphabet`.  Each symbol costs its rank, as for bytes, however
+/// large the alphabet.
+pub fn encode_symbols(data: &[u32], alphabet: usize) -> Vec<u32> {
+    let mut vals = (0..alphabet as u32).collect::<Vec<u32>>();
 
-        let c = vals.remove(i as usize);
-        vals.insert(0, c);
-    };
+    data.iter().map(|&c| move_to_front(&mut vals, c) as u32).collect()
+}
 
-    out
+pub fn decode_symbols(data: &[u32], alphabet: usize) -> Vec<u32> {
+    let mut vals = (0..alphabet as u32).collect::<Vec<u32>>();
+
+    data.iter().map(|&i| take_to_front(&mut vals, i as usize)).collect()
+}
+
+/// How the list is reordered after each byte.  All of them code a byte as its position in the
+/// list, so they share an inverse.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ListUpdate {
+    /// Move to front, as `encode`.
+    #[default]
+    Mtf,
+    /// Move to front from the second place, and to the second place from further back.  Keeps a
+    /// byte that occurs once from pushing back the one in front.
+    Mtf1,
+    /// Like `Mtf1`, but a byte in the second place only moves to the front if the byte before it
+    /// wasn't the front one, as in bzip2's experiments.
+    Mtf2,
+    /// Timestamp(0): move a byte in front of the first byte ahead of it that has been seen at
+    /// most once since the byte itself was last seen.
+    Timestamp,
+    /// Weighted frequency count: keep the list in order of how often each byte has been seen,
+    /// with each sighting weighing less the longer ago it was.
+    Wfc,
+}
+
+impl ListUpdate {
+    pub fn tag(self) -> u8 {
+        match self {
+            ListUpdate::Mtf => 0,
+            ListUpdate::Mtf1 => 1,
+            ListUpdate::Mtf2 => 2,
+            ListUpdate::Timestamp => 3,
+            ListUpdate::Wfc => 4,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<ListUpdate> {
+        match tag {
+            0 => Some(ListUpdate::Mtf),
+            1 => Some(ListUpdate::Mtf1),
+            2 => Some(ListUpdate::Mtf2),
+            3 => Some(ListUpdate::Timestamp),
+            4 => Some(ListUpdate::Wfc),
+            _ => None,
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> Vec<u8> {
+        if self == ListUpdate::Mtf {
+            return encode(data);
+        };
+
+        let mut list = List::new(self);
+        data.iter().map(|&c| list.encode(c) as u8).collect()
+    }
+
+    pub fn decode(self, data: &[u8]) -> Vec<u8> {
+        if self == ListUpdate::Mtf {
+            return decode(data);
+        };
+
+        let mut list = List::new(self);
+        data.iter().map(|&rank| {
+            let c = list.vals[rank as usize];
+            list.update(rank as usize);
+            c
+        }).collect()
+    }
+}
+
+// Each sighting weighs `1 + 1 / 2^WFC_GROWTH` times as much as one a byte earlier, which is the
+// same as every earlier weight shrinking.  Weights are scaled down together before they
+// overflow, which forgets sightings more than a few dozen bytes old.  Slower decay does better
+// on binaries and worse on text.
+const WFC_GROWTH: u32 = 1;
+const WFC_LIMIT: u64 = 1 << 48;
+
+// The list, and whatever the update rules need to remember.
+struct List {
+    method: ListUpdate,
+    vals: [u8; 256],
+    last_rank: usize,
+    // For `Timestamp`: when each byte was last seen, and the time before, counting from 1.
+    time: u64,
+    seen: [u64; 256],
+    seen_before: [u64; 256],
+    // For `Wfc`.
+    weights: [u64; 256],
+    increment: u64,
+}
+
+impl List {
+    fn new(method: ListUpdate) -> List {
+        List {
+            method,
+            vals: identity(),
+            last_rank: 0,
+            time: 0,
+            seen: [0; 256],
+            seen_before: [0; 256],
+            weights: [0; 256],
+            increment: 1 << 16,
+        }
+    }
+
+    // Puts the byte at `rank` at `to`, which is no further back.
+    fn move_to(&mut self, rank: usize, to: usize) {
+        let c = self.vals[rank];
+        self.vals.copy_within(to..rank, to + 1);
+        self.vals[to] = c;
+    }
+
+    // Codes `c` as its rank, reordering the list as `update` does.  Where `c` moves to doesn't
+    // depend on its rank, except for `Mtf1` and `Mtf2` deciding between the first two places, so
+    // the list is shifted along while looking for it, as `encode` does.
+    fn encode(&mut self, c: u8) -> usize {
+        self.see(c as usize);
+
+        let to = match self.method {
+            ListUpdate::Mtf => 0,
+            ListUpdate::Mtf1 | ListUpdate::Mtf2 => (self.vals[0] != c) as usize,
+            ListUpdate::Timestamp | ListUpdate::Wfc => self.vals.iter().
+                position(|&v| v == c || self.goes_before(c as usize, v as usize)).
+                unwrap(),
+        };
+        let rank = to + move_to_front(&mut self.vals[to..], c);
+
+        self.moved(c as usize, rank);
+        rank
+    }
+
+    // Reorders the list after the byte at `rank` was coded.
+    fn update(&mut self, rank: usize) {
+        let c = self.vals[rank] as usize;
+        self.see(c);
+
+        let to = match self.method {
+            ListUpdate::Mtf => 0,
+            ListUpdate::Mtf1 | ListUpdate::Mtf2 => rank.min(1),
+            ListUpdate::Timestamp | ListUpdate::Wfc => self.vals[..rank].iter().
+                position(|&v| self.goes_before(c, v as usize)).
+                unwrap_or(rank),
+        };
+        self.move_to(rank, to);
+
+        self.moved(c, rank);
+    }
+
+    // Counts a sighting of `c`, before it's moved.
+    fn see(&mut self, c: usize) {
+        match self.method {
+            ListUpdate::Timestamp => self.time += 1,
+            ListUpdate::Wfc => {
+                self.weights[c] += self.increment;
+                self.increment += self.increment >> WFC_GROWTH;
+            },
+            ListUpdate::Mtf | ListUpdate::Mtf1 | ListUpdate::Mtf2 => {},
+        };
+    }
+
+    // Whether `c` moves in front of `v` for `Timestamp` and `Wfc`.
+    fn goes_before(&self, c: usize, v: usize) -> bool {
+        match self.method {
+            // A byte seen for the first time stays where it is.
+            ListUpdate::Timestamp => self.seen[c] > 0 && self.seen_before[v] < self.seen[c],
+            ListUpdate::Wfc => self.weights[v] <= self.weights[c],
+            ListUpdate::Mtf | ListUpdate::Mtf1 | ListUpdate::Mtf2 => true,
+        }
+    }
+
+    // Finishes reordering after `c`, which was at `rank`, has been moved.
+    fn moved(&mut self, c: usize, rank: usize) {
+        match self.method {
+            // From the second place, `Mtf1` moves to the front, and `Mtf2` does if the byte
+            // before wasn't the front one.
+            ListUpdate::Mtf1 if rank == 1 => self.vals.swap(0, 1),
+            ListUpdate::Mtf2 if rank == 1 && self.last_rank != 0 => self.vals.swap(0, 1),
+            ListUpdate::Timestamp => {
+                self.seen_before[c] = self.seen[c];
+                self.seen[c] = self.time;
+            },
+            ListUpdate::Wfc if self.increment > WFC_LIMIT => {
+                for weight in self.weights.iter_mut() {
+                    *weight >>= 24;
+                };
+                self.increment >>= 24;
+            },
+            _ => {},
+        };
+
+        self.last_rank = rank;
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use bwt;
+    use testdata::Random;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn encode_test() {
@@ -53,4 +339,128 @@ mod test {
 
         assert_eq!(&expected_result, &result[0..]);
     }
+
+    #[test]
+    fn symbols() {
+        let data = [2, 2, 2, 2, 1, 5, 3, 2];
+        let wide = [700, 700, 3, 999, 700, 3];
+
+        let result = encode_symbols(&data, 6);
+        assert_eq!(result, [2, 0, 0, 0, 2, 5, 4, 3]);
+        assert_eq!(decode_symbols(&result, 6), data);
+
+        let result = encode_symbols(&wide, 1000);
+        assert_eq!(result, [700, 0, 4, 999, 2, 2]);
+        assert_eq!(decode_symbols(&result, 1000), wide);
+    }
+
+    #[test]
+    fn streaming() {
+        for input in inputs() {
+            let encoded = encode(&input);
+
+            for &size in &[1, 3, 100, 4096] {
+                let mut out = Vec::new();
+                let mut encoder = MtfEncoder::new();
+                for chunk in input.chunks(size) {
+                    encoder.push(chunk, &mut out);
+                };
+                encoder.finish(&mut out);
+                assert_eq!(out, encoded);
+
+                let mut out = Vec::new();
+                let mut decoder = MtfDecoder::new();
+                for chunk in encoded.chunks(size) {
+                    decoder.push(chunk, &mut out);
+                };
+                decoder.finish(&mut out);
+                assert_eq!(out, input);
+            };
+        };
+    }
+
+    #[test]
+    fn list_updates() {
+        let data = [2, 2, 2, 1, 2, 1, 2];
+        let expected: [(ListUpdate, [u8; 7]); 5] = [
+            (ListUpdate::Mtf, [2, 0, 0, 2, 1, 1, 1]),
+            (ListUpdate::Mtf1, [2, 1, 0, 2, 0, 1, 1]),
+            (ListUpdate::Mtf2, [2, 1, 0, 2, 0, 1, 0]),
+            (ListUpdate::Timestamp, [2, 2, 0, 2, 0, 2, 1]),
+            (ListUpdate::Wfc, [2, 0, 0, 2, 0, 1, 1]),
+        ];
+
+        for &(list_update, ranks) in expected.iter() {
+            assert_eq!(list_update.encode(&data), ranks, "{:?}", list_update);
+            assert_eq!(list_update.decode(&ranks), data);
+            assert_eq!(ListUpdate::from_tag(list_update.tag()), Some(list_update));
+        };
+
+        for input in inputs() {
+            for &(list_update, _) in expected.iter() {
+                assert_eq!(list_update.decode(&list_update.encode(&input)), input);
+            };
+        };
+    }
+
+    // The list kept in a `Vec`, moving each byte with `remove` and `insert`.
+    fn naive_encode(data: &[u8]) -> Vec<u8> {
+        let mut vals = (0..256).map(|i| i as u8).collect::<Vec<u8>>();
+
+        data.iter().map(|&c| {
+            let idx = vals.iter().position(|&v| v == c).unwrap();
+            vals.remove(idx);
+            vals.insert(0, c);
+            idx as u8
+        }).collect()
+    }
+
+    fn naive_decode(data: &[u8]) -> Vec<u8> {
+        let mut vals = (0..256).map(|i| i as u8).collect::<Vec<u8>>();
+
+        data.iter().map(|&i| {
+            let c = vals.remove(i as usize);
+            vals.insert(0, c);
+            c
+        }).collect()
+    }
+
+    fn inputs() -> Vec<Vec<u8>> {
+        vec![
+            Vec::new(),
+            (0..=255).rev().collect(),
+            Random::new(3).bytes(20_000, 256),
+            bwt::encode(include_bytes!("mtf.rs").repeat(50).as_slice()),
+        ]
+    }
+
+    #[test]
+    fn matches_naive() {
+        for input in inputs() {
+            let encoded = encode(&input);
+
+            assert_eq!(encoded, naive_encode(&input));
+            assert_eq!(decode(&input), naive_decode(&input));
+            assert_eq!(decode(&encoded), input);
+        };
+    }
+
+    // cargo test --release bench -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench() {
+        fn time<F: Fn(&[u8]) -> Vec<u8>>(f: F, data: &[u8]) -> Duration {
+            let start = Instant::now();
+            for _ in 0..10 {
+                f(data);
+            };
+            start.elapsed() / 10
+        }
+
+        for input in inputs().iter().skip(2) {
+            println!("{} bytes: encode {:?} (was {:?}), decode {:?} (was {:?})", input.len(),
+                     time(encode, input), time(naive_encode, input),
+                     time(decode, input), time(naive_decode, input));
+        };
+    }
 }
@@ -0,0 +1,610 @@
+use bwt;
+use distance;
+use bwt::{DecodeMode, FmIndex, SampledSa};
+use entropy::{Backend, BlockDecoder, BlockEncoder};
+use mtf::ListUpdate;
+use range;
+use rle;
+use std::io;
+use std::io::Write;
+use std::io::Read;
+use std::iter;
+use std::str;
+use unicode;
+use byteorder::{ReadBytesExt, WriteBytesExt};
+
+/// What happens to a block before entropy coding.  This is written as the first byte of each
+/// block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// `bwt::encode`, then `Options::list_update` and `rle::encode`.
+    Bwt,
+    /// Nothing; the entropy coder sees the raw bytes.
+    Raw,
+    /// `bwt::encode_bijective`, then as `Bwt`.  Saves the primary index, but sorts more slowly.
+    Bijective,
+    /// `bwt::encode_schindler` on contexts of `Options::context_order` bytes, then as `Bwt`.
+    /// Sorts in linear time, for a somewhat worse ratio.
+    Schindler,
+    /// `unicode::encode`, for UTF-8 text.  It does its own entropy coding, so `Options::backend`
+    /// isn't used.  Blocks that aren't valid UTF-8 go through `Transform::Bwt` instead; see
+    /// `Options::blocks` for cutting text so that they are.
+    Unicode,
+    /// `bwt::encode`, then `distance::encode_distances` in place of the MTF list and
+    /// `range::encode_gaps`.  Also does its own entropy coding.
+    Distances,
+    /// `bwt::encode`, then `distance::encode_inversions` in place of the MTF list and
+    /// `range::encode_gaps`.  Also does its own entropy coding.
+    Inversions,
+}
+
+impl Transform {
+    fn tag(self) -> u8 {
+        match self {
+            Transform::Bwt => 0,
+            Transform::Raw => 1,
+            Transform::Bijective => 2,
+            Transform::Schindler => 3,
+            Transform::Unicode => 4,
+            Transform::Distances => 5,
+            Transform::Inversions => 6,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Transform> {
+        match tag {
+            0 => Some(Transform::Bwt),
+            1 => Some(Transform::Raw),
+            2 => Some(Transform::Bijective),
+            3 => Some(Transform::Schindler),
+            4 => Some(Transform::Unicode),
+            5 => Some(Transform::Distances),
+            6 => Some(Transform::Inversions),
+            _ => None,
+        }
+    }
+
+    // Returns the transform actually used, as `Unicode` falls back to `Bwt`, and suffix array
+    // samples, if asked for and the transform has any.
+    fn encode(self, data: &[u8], options: &Options) -> (Transform, Vec<u8>, Option<SampledSa>) {
+        let list_update = options.list_update;
+
+        let (transformed, samples) = match self {
+            Transform::Bwt => {
+                let (bwt, samples) = bwt::encode_sampled(data, options.entry_points,
+                                                         options.sample_rate);
+                let samples = if options.sample_rate > 0 { Some(samples) } else { None };
+                (rle::encode(&list_update.encode(&bwt)), samples)
+            },
+            Transform::Raw => (data.to_vec(), None),
+            Transform::Bijective =>
+                (rle::encode(&list_update.encode(&bwt::encode_bijective(data))), None),
+            Transform::Schindler => {
+                let st = bwt::encode_schindler(data, options.context_order);
+                (rle::encode(&list_update.encode(&st)), None)
+            },
+            Transform::Unicode => match str::from_utf8(data) {
+                Ok(text) => (unicode::encode(text), None),
+                Err(_) => return Transform::Bwt.encode(data, options),
+            },
+            Transform::Distances => {
+                let bwt = bwt::encode_with_entries(data, options.entry_points);
+                (range::encode_gaps(&distance::encode_distances(&bwt)), None)
+            },
+            Transform::Inversions => {
+                let bwt = bwt::encode_with_entries(data, options.entry_points);
+                (range::encode_gaps(&distance::encode_inversions(&bwt)), None)
+            },
+        };
+
+        (self, transformed, samples)
+    }
+
+    // Whether the transform's output is already entropy coded.
+    fn codes_itself(self) -> bool {
+        match self {
+            Transform::Unicode | Transform::Distances | Transform::Inversions => true,
+            Transform::Bwt | Transform::Raw | Transform::Bijective | Transform::Schindler => false,
+        }
+    }
+
+    // Whether the transform goes through `Options::list_update`.  The others have 0 in its place
+    // in the tag.
+    fn lists(self) -> bool {
+        match self {
+            Transform::Bwt | Transform::Bijective | Transform::Schindler => true,
+            Transform::Raw | Transform::Unicode | Transform::Distances | Transform::Inversions =>
+                false,
+        }
+    }
+
+    fn decode(self, data: &[u8], list_update: ListUpdate, mode: DecodeMode,
+              threads: usize) -> io::Result<Vec<u8>> {
+        let unlisted = || unlist(data, list_update);
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        Ok(match self {
+            Transform::Bwt => bwt::decode_parallel(&unlisted()?, mode, threads)?,
+            Transform::Raw => data.to_vec(),
+            Transform::Bijective => bwt::decode_bijective(&unlisted()?),
+            Transform::Schindler => bwt::decode_schindler(&unlisted()?)?,
+            Transform::Unicode => unicode::decode(data).map_err(invalid)?,
+            Transform::Distances => {
+                let distances = range::decode_gaps(data).map_err(invalid)?;
+                let bwt = distance::decode_distances(&distances).map_err(invalid)?;
+                bwt::decode_parallel(&bwt, mode, threads)?
+            },
+            Transform::Inversions => {
+                let inversions = range::decode_gaps(data).map_err(invalid)?;
+                let bwt = distance::decode_inversions(&inversions).map_err(invalid)?;
+                bwt::decode_parallel(&bwt, mode, threads)?
+            },
+        })
+    }
+}
+
+// Undoes `rle::encode` and the list update, leaving the last column of a block's BWT.
+fn unlist(data: &[u8], list_update: ListUpdate) -> io::Result<Vec<u8>> {
+    match rle::decode(data) {
+        Ok(runs) => Ok(list_update.decode(&runs)),
+        Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+}
+
+/// A block read for searching: indexed if it was stored with suffix array samples, so it can be
+/// searched without decoding, and decoded if not.
+pub enum Block {
+    Decoded(Vec<u8>),
+    Indexed(Box<FmIndex>),
+}
+
+// Set in the transform tag of blocks followed by suffix array samples.
+const SAMPLED: u8 = 0x80;
+
+// Where the list update's tag goes in the transform tag.
+const LIST_UPDATE_SHIFT: u8 = 4;
+const LIST_UPDATE_MASK: u8 = 0x70;
+
+// How often to sample blocks that were stored without samples, when indexing them.
+const DEFAULT_SAMPLE_RATE: usize = 32;
+
+struct Transformed {
+    transform: Transform,
+    list_update: ListUpdate,
+    data: Vec<u8>,
+    samples: Option<SampledSa>,
+}
+
+impl Transformed {
+    fn decode(&self, mode: DecodeMode, threads: usize) -> io::Result<Vec<u8>> {
+        self.transform.decode(&self.data, self.list_update, mode, threads)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    pub block_size: usize,
+    pub transform: Transform,
+    pub backend: Backend,
+    /// Extra places to start undoing each block's BWT from, letting the decoder use as many
+    /// threads.  Costs four bytes each.
+    pub entry_points: usize,
+    /// How often to sample each block's suffix array, so an `FmIndex` can be built without
+    /// walking the block.  0 for no samples; otherwise they cost `4 / sample_rate` bytes a byte.
+    pub sample_rate: usize,
+    /// How many bytes of context `Transform::Schindler` sorts rows on.  Kept in each block.
+    pub context_order: u8,
+    /// How `Bwt`, `Bijective` and `Schindler` reorder their MTF list.  Kept in each block.
+    pub list_update: ListUpdate,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            block_size: 900_000,
+            transform: Transform::Bwt,
+            backend: Backend::Huffman,
+            entry_points: 0,
+            sample_rate: 0,
+            context_order: 4,
+            list_update: ListUpdate::Mtf,
+        }
+    }
+}
+
+impl Options {
+    /// Skips the BWT and codes each byte using the byte before it as context.  Much faster, for
+    /// a worse ratio.
+    pub fn fast() -> Options {
+        Options { transform: Transform::Raw, backend: Backend::Order1Huffman, ..Options::default() }
+    }
+
+    /// Cuts `data` into blocks of at most `block_size` bytes.  For `Transform::Unicode`, a block
+    /// that would split a character ends before it instead, so UTF-8 text stays UTF-8 in every
+    /// block.
+    pub fn blocks<'a>(&self, data: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        assert!(self.block_size != 0, "block size must be nonzero");
+
+        let block_size = self.block_size;
+        let unicode = self.transform == Transform::Unicode;
+        let mut rest = data;
+
+        iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            };
+
+            let mut end = rest.len().min(block_size);
+            if unicode && end < rest.len() {
+                // A character is at most four bytes, so its start is at most three back.  Don't
+                // back up to the start of the block, or it would never end.
+                let first = end.saturating_sub(3).max(1);
+                if let Some(start) = (first..end + 1).rev().find(|&i| rest[i] & 0xc0 != 0x80) {
+                    end = start;
+                };
+            };
+
+            let (block, tail) = rest.split_at(end);
+            rest = tail;
+            Some(block)
+        })
+    }
+}
+
+pub struct Compressor {
+    options: Options,
+    encoder: BlockEncoder,
+    // For blocks the transform has already coded.
+    stored: BlockEncoder,
+}
+
+impl Compressor {
+    pub fn new(options: Options) -> Compressor {
+        Compressor {
+            options,
+            encoder: BlockEncoder::new(options.backend),
+            stored: BlockEncoder::new(Backend::Stored),
+        }
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Compresses one block, which should be no longer than `block_size`.
+    pub fn write_block(&mut self, data: &[u8], writer: &mut dyn Write) -> io::Result<usize> {
+        let (transform, transformed, samples) = self.options.transform.encode(data, &self.options);
+        let encoder = if transform.codes_itself() { &mut self.stored } else { &mut self.encoder };
+
+        let list_update =
+            if transform.lists() { self.options.list_update } else { ListUpdate::Mtf };
+        let tag = transform.tag() | list_update.tag() << LIST_UPDATE_SHIFT;
+
+        match samples {
+            Some(samples) => {
+                writer.write_u8(tag | SAMPLED)?;
+                let bytes_out = encoder.write_block(&transformed, writer)?;
+                Ok(1 + bytes_out + samples.write(writer)?)
+            },
+            None => {
+                writer.write_u8(tag)?;
+                Ok(1 + encoder.write_block(&transformed, writer)?)
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Decompressor {
+    decoder: BlockDecoder,
+    mode: DecodeMode,
+    threads: usize,
+}
+
+impl Decompressor {
+    pub fn new() -> Decompressor {
+        Decompressor::with_mode(DecodeMode::default())
+    }
+
+    /// A decompressor that undoes the BWT with `mode`.
+    pub fn with_mode(mode: DecodeMode) -> Decompressor {
+        Decompressor::with_threads(mode, 1)
+    }
+
+    /// Like `with_mode`, but using up to `threads` threads for blocks with entry points.
+    pub fn with_threads(mode: DecodeMode, threads: usize) -> Decompressor {
+        Decompressor { decoder: BlockDecoder::new(), mode, threads }
+    }
+
+    // Reads a block up to undoing its transform.
+    fn read_transformed(&mut self, reader: &mut dyn Read) -> io::Result<Option<Transformed>> {
+        let tag = match reader.read_u8() {
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+            Ok(tag) => tag,
+        };
+
+        let transform = Transform::from_tag(tag & !(SAMPLED | LIST_UPDATE_MASK));
+        let list_update = ListUpdate::from_tag((tag & LIST_UPDATE_MASK) >> LIST_UPDATE_SHIFT);
+        let (transform, list_update) = match (transform, list_update) {
+            (Some(transform), Some(list_update))
+                if transform.lists() || list_update == ListUpdate::Mtf => (transform, list_update),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                           format!("Unknown transform {}", tag))),
+        };
+
+        let data = match self.decoder.read_block(reader)? {
+            Some(data) => data,
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated block")),
+        };
+
+        let samples = if tag & SAMPLED != 0 { Some(SampledSa::read(reader)?) } else { None };
+
+        Ok(Some(Transformed { transform, list_update, data, samples }))
+    }
+
+    pub fn read_block(&mut self, reader: &mut dyn Read) -> io::Result<Option<Vec<u8>>> {
+        match self.read_transformed(reader)? {
+            Some(block) => Ok(Some(block.decode(self.mode, self.threads)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_searchable(&mut self, reader: &mut dyn Read) -> io::Result<Option<Block>> {
+        let block = match self.read_transformed(reader)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        Ok(Some(match block.samples {
+            Some(ref samples) if block.transform == Transform::Bwt => {
+                let bwt = unlist(&block.data, block.list_update)?;
+                Block::Indexed(Box::new(FmIndex::with_samples(&bwt, samples)?))
+            },
+            _ => Block::Decoded(block.decode(self.mode, self.threads)?),
+        }))
+    }
+
+    /// Reads the next block as an `FmIndex` instead of decoding it, using the block's suffix
+    /// array samples if it has them.  Only blocks that went through `Transform::Bwt` can be
+    /// indexed.
+    pub fn read_index(&mut self, reader: &mut dyn Read) -> io::Result<Option<FmIndex>> {
+        let block = match self.read_transformed(reader)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+
+        if block.transform != Transform::Bwt {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Can't index a {:?} block", block.transform)));
+        };
+
+        let bwt = unlist(&block.data, block.list_update)?;
+        Ok(Some(match block.samples {
+            Some(samples) => FmIndex::with_samples(&bwt, &samples)?,
+            None => FmIndex::new(&bwt, DEFAULT_SAMPLE_RATE)?,
+        }))
+    }
+}
+
+pub fn compress(data: &[u8], options: Options, writer: &mut dyn Write) -> io::Result<usize> {
+    let mut compressor = Compressor::new(options);
+    let mut bytes_out = 0;
+
+    // An empty input still gets a block, so the stream isn't empty too.
+    if data.is_empty() {
+        return compressor.write_block(data, writer);
+    };
+
+    for block in options.blocks(data) {
+        bytes_out += compressor.write_block(block, writer)?;
+    };
+
+    Ok(bytes_out)
+}
+
+pub fn decompress(reader: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut decompressor = Decompressor::new();
+    let mut bytes = Vec::new();
+
+    while let Some(block) = decompressor.read_block(reader)? {
+        bytes.extend_from_slice(&block);
+    };
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt::DecodeMode;
+    use entropy::Backend;
+    use mtf::ListUpdate;
+    use pipeline::{compress, decompress, Compressor, Decompressor, Options, Transform};
+    use std::io;
+
+    #[test]
+    fn round_trip() {
+        let data = include_bytes!("pipeline.rs");
+        let fast = Options { block_size: 1000, ..Options::fast() };
+        let raw = Options { transform: Transform::Raw, backend: Backend::Range, ..fast };
+        let entries = Options { entry_points: 3, ..Options::default() };
+        let bijective = Options { transform: Transform::Bijective, ..Options::default() };
+        let schindler = Options { transform: Transform::Schindler, context_order: 3,
+                                  ..Options::default() };
+        let unicode = Options { transform: Transform::Unicode, block_size: 5000,
+                                ..Options::default() };
+        let wfc = Options { list_update: ListUpdate::Wfc, ..Options::default() };
+        let distances = Options { transform: Transform::Distances, ..entries };
+        let inversions = Options { transform: Transform::Inversions, ..Options::default() };
+
+        for &options in &[Options::default(), fast, raw, entries, bijective, schindler, unicode,
+                          wfc, distances, inversions] {
+            let mut buf = Vec::new();
+            compress(data, options, &mut buf).unwrap();
+
+            assert!(buf.len() < data.len());
+            assert_eq!(&decompress(&mut &buf[..]).unwrap()[..], &data[..]);
+        };
+    }
+
+    #[test]
+    fn tiny_blocks() {
+        let transforms = [Transform::Bwt, Transform::Raw, Transform::Bijective,
+                          Transform::Schindler, Transform::Unicode, Transform::Distances,
+                          Transform::Inversions];
+        let backends = [Backend::Huffman, Backend::AdaptiveHuffman, Backend::Range, Backend::Rans,
+                        Backend::Context, Backend::Order1Huffman];
+
+        for len in 0..17 {
+            let data = b"mississippi river".iter().take(len).cloned().collect::<Vec<u8>>();
+
+            for &transform in transforms.iter() {
+                for &backend in backends.iter() {
+                    let options = Options { transform, backend, sample_rate: 4,
+                                            ..Options::default() };
+                    let mut buf = Vec::new();
+                    compress(&data, options, &mut buf).unwrap();
+
+                    assert!(!buf.is_empty());
+                    assert_eq!(decompress(&mut &buf[..]).unwrap(), data);
+                };
+            };
+        };
+    }
+
+    #[test]
+    fn unicode_falls_back_to_bytes() {
+        let text = "Grüße aus Köln. ".repeat(20).into_bytes();
+        let bytes = b"caf\xe9 au lait".to_vec();
+        let options = Options { transform: Transform::Unicode, ..Options::default() };
+        let mut compressor = Compressor::new(options);
+
+        for &(block, transform) in &[(&text, Transform::Unicode), (&bytes, Transform::Bwt)] {
+            let mut buf = Vec::new();
+            compressor.write_block(block, &mut buf).unwrap();
+
+            assert_eq!(buf[0], transform.tag());
+            assert_eq!(&decompress(&mut &buf[..]).unwrap(), block);
+        };
+    }
+
+    #[test]
+    fn unicode_blocks_end_on_characters() {
+        let text = "Съешь же ещё этих мягких французских булок. 我能吞下玻璃而不伤身体。🦀 ".
+            repeat(20).
+            into_bytes();
+
+        // Anything shorter than four bytes can't always hold a character.
+        for &block_size in &[4, 5, 100, 997] {
+            let options = Options { transform: Transform::Unicode, block_size,
+                                    ..Options::default() };
+            let mut buf = Vec::new();
+            compress(&text, options, &mut buf).unwrap();
+
+            let mut decompressor = Decompressor::new();
+            let mut reader = &buf[..];
+            let mut blocks = 0;
+            while let Some(block) = decompressor.read_transformed(&mut reader).unwrap() {
+                assert_eq!(block.transform, Transform::Unicode);
+                blocks += 1;
+            };
+
+            assert!(blocks > 1);
+            assert_eq!(decompress(&mut &buf[..]).unwrap(), text);
+        };
+    }
+
+    #[test]
+    fn list_updates() {
+        let data = include_bytes!("pipeline.rs");
+        let updates = [ListUpdate::Mtf, ListUpdate::Mtf1, ListUpdate::Mtf2, ListUpdate::Timestamp,
+                       ListUpdate::Wfc];
+
+        for &list_update in updates.iter() {
+            let options = Options { block_size: 4000, list_update, ..Options::default() };
+            let mut buf = Vec::new();
+            compress(data, options, &mut buf).unwrap();
+
+            assert_eq!(buf[0] >> 4, list_update.tag());
+            assert_eq!(&decompress(&mut &buf[..]).unwrap()[..], &data[..]);
+        };
+
+        // Transforms without a list leave it out of the tag, and readers don't accept one.
+        for &transform in &[Transform::Raw, Transform::Unicode, Transform::Distances,
+                            Transform::Inversions] {
+            let options = Options { transform, list_update: ListUpdate::Wfc, ..Options::default() };
+            let mut buf = Vec::new();
+            compress(b"banana", options, &mut buf).unwrap();
+
+            assert_eq!(buf[0], transform.tag());
+            buf[0] |= ListUpdate::Wfc.tag() << 4;
+            let err = decompress(&mut &buf[..]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        };
+    }
+
+    #[test]
+    fn small_decode() {
+        let data = include_bytes!("pipeline.rs");
+        let mut buf = Vec::new();
+        compress(data, Options { block_size: 2000, ..Options::default() }, &mut buf).unwrap();
+
+        let mut decompressor = Decompressor::with_mode(DecodeMode::Small);
+        let mut reader = &buf[..];
+        let mut out = Vec::new();
+        while let Some(block) = decompressor.read_block(&mut reader).unwrap() {
+            out.extend(block);
+        };
+
+        assert_eq!(&out[..], &data[..]);
+    }
+
+    #[test]
+    fn threaded_decode() {
+        let data = include_bytes!("pipeline.rs");
+        let options = Options { block_size: 2000, entry_points: 2, ..Options::default() };
+        let mut buf = Vec::new();
+        compress(data, options, &mut buf).unwrap();
+
+        let mut decompressor = Decompressor::with_threads(DecodeMode::Small, 2);
+        let mut reader = &buf[..];
+        let mut out = Vec::new();
+        while let Some(block) = decompressor.read_block(&mut reader).unwrap() {
+            out.extend(block);
+        };
+
+        assert_eq!(&out[..], &data[..]);
+    }
+
+    #[test]
+    fn search_blocks() {
+        let data = include_bytes!("pipeline.rs");
+        let pattern = b"options";
+        let expected = (0..data.len() - pattern.len()).
+            filter(|&i| &data[i..i + pattern.len()] == pattern).
+            collect::<Vec<usize>>();
+
+        for &sample_rate in &[0, 16] {
+            let options = Options { block_size: 3000, sample_rate, ..Options::default() };
+            let mut buf = Vec::new();
+            compress(data, options, &mut buf).unwrap();
+
+            let mut decompressor = Decompressor::new();
+            let mut reader = &buf[..];
+            let mut offset = 0;
+            let mut found = Vec::new();
+            while let Some(index) = decompressor.read_index(&mut reader).unwrap() {
+                found.extend(index.locate(pattern).unwrap().iter().map(|pos| offset + pos));
+                offset += index.len();
+            };
+
+            // Matches across block boundaries aren't found.
+            let within = expected.iter().
+                filter(|&&pos| pos / 3000 == (pos + pattern.len() - 1) / 3000).
+                cloned().
+                collect::<Vec<usize>>();
+            assert_eq!(found, within);
+            assert_eq!(&decompress(&mut &buf[..]).unwrap()[..], &data[..]);
+        };
+    }
+}
@@ -0,0 +1,539 @@
+// A range coder (the carry-propagating variant used by LZMA) with an adaptive order-0 model.
+// Unlike Huffman it can spend less than a bit on a symbol, which matters for the output of
+// `mtf::encode`, where a single rank is often more likely than all the others put together.
+use byteorder::{BigEndian, ByteOrder};
+
+const TOP: u32 = 1 << 24;
+
+/// Precision of the probabilities passed to `encode_bit` and `decode_bit`.
+pub const BIT_BITS: u32 = 12;
+pub const BIT_SCALE: u32 = 1 << BIT_BITS;
+
+pub struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: usize,
+    out: Vec<u8>,
+}
+
+impl Default for RangeEncoder {
+    fn default() -> RangeEncoder {
+        RangeEncoder::new()
+    }
+}
+
+impl RangeEncoder {
+    pub fn new() -> RangeEncoder {
+        RangeEncoder { low: 0, range: 0xffff_ffff, cache: 0, cache_size: 1, out: Vec::new() }
+    }
+
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+
+            loop {
+                self.out.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                };
+            };
+
+            self.cache = (self.low >> 24) as u8;
+        };
+
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    /// Codes the interval `[cum, cum + freq)` out of `total`, which must be below `TOP`.
+    pub fn encode(&mut self, cum: u32, freq: u32, total: u32) {
+        let r = self.range / total;
+        self.low += (cum * r) as u64;
+        self.range = freq * r;
+
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        };
+    }
+
+    /// Codes one bit, where `p0` out of `BIT_SCALE` is the probability of a 0.
+    pub fn encode_bit(&mut self, p0: u32, bit: u8) {
+        let bound = (self.range >> BIT_BITS) * p0;
+
+        if bit == 0 {
+            self.range = bound;
+        } else {
+            self.low += bound as u64;
+            self.range -= bound;
+        };
+
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        };
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        };
+
+        self.out
+    }
+}
+
+pub struct RangeDecoder<'a> {
+    code: u32,
+    range: u32,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RangeDecoder<'a> {
+    pub fn new(data: &'a [u8]) -> RangeDecoder<'a> {
+        let mut decoder = RangeDecoder { code: 0, range: 0xffff_ffff, data, pos: 0 };
+
+        for _ in 0..5 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte() as u32;
+        };
+
+        decoder
+    }
+
+    // Past the end of the input, reads as zeroes; the encoder's flush makes that safe.
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.data.get(self.pos).cloned().unwrap_or(0);
+        self.pos += 1;
+        byte
+    }
+
+    fn normalize(&mut self) {
+        while self.range < TOP {
+            self.code = (self.code << 8) | self.next_byte() as u32;
+            self.range <<= 8;
+        };
+    }
+
+    /// The cumulative frequency the next symbol falls at.  Must be followed by `decode_update`.
+    pub fn decode_freq(&mut self, total: u32) -> u32 {
+        self.range /= total;
+        (self.code / self.range).min(total - 1)
+    }
+
+    pub fn decode_update(&mut self, cum: u32, freq: u32) {
+        self.code -= cum * self.range;
+        self.range *= freq;
+        self.normalize();
+    }
+
+    pub fn decode_bit(&mut self, p0: u32) -> u8 {
+        let bound = (self.range >> BIT_BITS) * p0;
+
+        let bit = if self.code < bound {
+            self.range = bound;
+            0
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            1
+        };
+
+        self.normalize();
+        bit
+    }
+
+    /// Whether the decoder has read beyond the end of its input.  A complete stream is always
+    /// read one byte past its end, since the encoder never writes its final cached byte.
+    pub fn overrun(&self) -> bool {
+        self.pos > self.data.len() + 1
+    }
+}
+
+const INCREMENT: u32 = 24;
+const MAX_TOTAL: u32 = 1 << 16;
+
+/// Adaptive frequencies over an alphabet of any size, with cumulative counts in a Fenwick tree.
+#[derive(Clone)]
+pub struct AdaptiveModel {
+    freqs: Vec<u32>,
+    tree: Vec<u32>,
+    total: u32,
+}
+
+impl AdaptiveModel {
+    pub fn new(symbols: usize) -> AdaptiveModel {
+        let mut model = AdaptiveModel {
+            freqs: vec![1; symbols],
+            tree: vec![0; symbols + 1],
+            total: 0,
+        };
+        model.rebuild();
+        model
+    }
+
+    fn rebuild(&mut self) {
+        for t in self.tree.iter_mut() {
+            *t = 0;
+        };
+
+        for i in 0..self.freqs.len() {
+            let freq = self.freqs[i];
+            self.add(i, freq);
+        };
+
+        self.total = self.freqs.iter().sum();
+    }
+
+    fn add(&mut self, sym: usize, delta: u32) {
+        let mut i = sym + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        };
+    }
+
+    // Sum of the frequencies of symbols below `sym`.
+    fn cum(&self, sym: usize) -> u32 {
+        let mut i = sym;
+        let mut sum = 0;
+        while i > 0 {
+            sum += self.tree[i];
+            i -= i & i.wrapping_neg();
+        };
+        sum
+    }
+
+    // The symbol whose interval holds `target`, and the start of that interval.
+    fn find(&self, mut target: u32) -> (usize, u32) {
+        let mut pos = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        let mut cum = 0;
+
+        while step > 0 {
+            let next = pos + step;
+            if next < self.tree.len() && self.tree[next] <= target {
+                pos = next;
+                target -= self.tree[next];
+                cum += self.tree[next];
+            };
+            step >>= 1;
+        };
+
+        (pos, cum)
+    }
+
+    fn update(&mut self, sym: usize) {
+        self.freqs[sym] += INCREMENT;
+        self.add(sym, INCREMENT);
+        self.total += INCREMENT;
+
+        if self.total > MAX_TOTAL {
+            for freq in self.freqs.iter_mut() {
+                *freq = freq.div_ceil(2);
+            };
+            self.rebuild();
+        };
+    }
+
+    pub fn encode(&mut self, encoder: &mut RangeEncoder, sym: usize) {
+        encoder.encode(self.cum(sym), self.freqs[sym], self.total);
+        self.update(sym);
+    }
+
+    pub fn decode(&mut self, decoder: &mut RangeDecoder) -> usize {
+        let target = decoder.decode_freq(self.total);
+        let (sym, cum) = self.find(target);
+
+        decoder.decode_update(cum, self.freqs[sym]);
+        self.update(sym);
+
+        sym
+    }
+}
+
+/// Codes `data` with an order-0 model.  The output starts with the number of symbols as a
+/// big-endian u32.
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut model = AdaptiveModel::new(256);
+    let mut encoder = RangeEncoder::new();
+
+    for &c in data {
+        model.encode(&mut encoder, c as usize);
+    };
+
+    let mut buf = vec![0; 4];
+    BigEndian::write_u32(&mut buf, data.len() as u32);
+    buf.extend(encoder.finish());
+
+    buf
+}
+
+pub fn decode(buf: &[u8]) -> Result<Vec<u8>, String> {
+    if buf.len() < 4 {
+        return Err(String::from("Range coded block is too short"));
+    };
+
+    // The length isn't trusted until the symbols are there, so only reserve what a well
+    // compressed block of this size might hold.
+    let len = BigEndian::read_u32(&buf[0..4]) as usize;
+    let mut model = AdaptiveModel::new(256);
+    let mut decoder = RangeDecoder::new(&buf[4..]);
+    let mut out = Vec::with_capacity(len.min(buf.len() * 8));
+
+    for _ in 0..len {
+        out.push(model.decode(&mut decoder) as u8);
+
+        if decoder.overrun() {
+            return Err(String::from("Range coded block is truncated"));
+        };
+    };
+
+    Ok(out)
+}
+
+// Symbols of `encode_wide` below this are coded as they are; larger ones as their length in bits,
+// then the bits after the first.
+const DIRECT: usize = 256;
+
+/// Like `encode`, but for symbols of any size, such as the ranks `mtf::encode_symbols` gives over
+/// a large alphabet.  Small symbols are modelled as in `encode`.  Larger ones only have their
+/// length in bits modelled, and the rest of their bits are sent as they are.
+pub fn encode_wide(data: &[u32]) -> Vec<u8> {
+    let mut model = AdaptiveModel::new(DIRECT + 33);
+    let mut encoder = RangeEncoder::new();
+
+    for &sym in data {
+        if (sym as usize) < DIRECT {
+            model.encode(&mut encoder, sym as usize);
+            continue;
+        };
+
+        let bits = 32 - sym.leading_zeros();
+        model.encode(&mut encoder, DIRECT + bits as usize);
+        for bit in (0..bits - 1).rev() {
+            encoder.encode_bit(BIT_SCALE / 2, (sym >> bit) as u8 & 1);
+        };
+    };
+
+    let mut buf = vec![0; 4];
+    BigEndian::write_u32(&mut buf, data.len() as u32);
+    buf.extend(encoder.finish());
+
+    buf
+}
+
+pub fn decode_wide(buf: &[u8]) -> Result<Vec<u32>, String> {
+    if buf.len() < 4 {
+        return Err(String::from("Range coded block is too short"));
+    };
+
+    // As in `decode`, the length isn't trusted until the symbols are there.
+    let len = BigEndian::read_u32(&buf[0..4]) as usize;
+    let mut model = AdaptiveModel::new(DIRECT + 33);
+    let mut decoder = RangeDecoder::new(&buf[4..]);
+    let mut out = Vec::with_capacity(len.min(buf.len() * 8));
+
+    for _ in 0..len {
+        let sym = model.decode(&mut decoder);
+        if sym < DIRECT {
+            out.push(sym as u32);
+        } else {
+            let bits = (sym - DIRECT).max(1);
+            let val = (0..bits - 1).
+                fold(1, |val, _| val << 1 | decoder.decode_bit(BIT_SCALE / 2) as u32);
+            out.push(val);
+        };
+
+        if decoder.overrun() {
+            return Err(String::from("Range coded block is truncated"));
+        };
+    };
+
+    Ok(out)
+}
+
+// Symbols of `encode_gaps` are coded as their length in bits, with the length of the symbol
+// before as context, then the bits after the first.  Bits near the top are modelled, for each
+// length, and the rest are sent as they are.
+const LENGTHS: usize = 33;
+const MODELLED_BITS: usize = 64;
+
+fn bit_length(sym: u32) -> usize {
+    (32 - sym.leading_zeros()) as usize
+}
+
+// Nudges `p0` towards the bit just coded.
+fn adapt(p0: &mut u32, bit: u8) {
+    if bit == 0 {
+        *p0 += (BIT_SCALE - *p0) >> 4;
+    } else {
+        *p0 -= *p0 >> 4;
+    };
+}
+
+struct GapModel {
+    lengths: Vec<AdaptiveModel>,
+    // A binary tree of probabilities for the top bits after the first, for each length.
+    bits: Vec<[u32; MODELLED_BITS]>,
+    previous: usize,
+}
+
+impl GapModel {
+    fn new() -> GapModel {
+        GapModel {
+            lengths: vec![AdaptiveModel::new(LENGTHS); LENGTHS],
+            bits: vec![[BIT_SCALE / 2; MODELLED_BITS]; LENGTHS],
+            previous: 0,
+        }
+    }
+
+    fn encode(&mut self, encoder: &mut RangeEncoder, sym: u32) {
+        let len = bit_length(sym);
+        self.lengths[self.previous].encode(encoder, len);
+        self.previous = len;
+
+        let mut node = 1;
+        for shift in (0..len.saturating_sub(1)).rev() {
+            let bit = (sym >> shift) as u8 & 1;
+            match self.bits[len].get_mut(node) {
+                Some(p0) => {
+                    encoder.encode_bit(*p0, bit);
+                    adapt(p0, bit);
+                },
+                None => encoder.encode_bit(BIT_SCALE / 2, bit),
+            };
+            node = node * 2 + bit as usize;
+        };
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder) -> u32 {
+        let len = self.lengths[self.previous].decode(decoder);
+        self.previous = len;
+
+        let mut node = 1;
+        let mut sym = if len > 0 { 1 } else { 0 };
+        for _ in 1..len {
+            let bit = match self.bits[len].get_mut(node) {
+                Some(p0) => {
+                    let bit = decoder.decode_bit(*p0);
+                    adapt(p0, bit);
+                    bit
+                },
+                None => decoder.decode_bit(BIT_SCALE / 2),
+            };
+            node = node * 2 + bit as usize;
+            sym = sym << 1 | bit as u32;
+        };
+
+        sym
+    }
+}
+
+/// Like `encode_wide`, but modelling every symbol by its length and the length of the one
+/// before, which suits the output of `distance::encode_distances` and
+/// `distance::encode_inversions`: mostly small, but with a long tail.
+pub fn encode_gaps(data: &[u32]) -> Vec<u8> {
+    let mut model = GapModel::new();
+    let mut encoder = RangeEncoder::new();
+
+    for &sym in data {
+        model.encode(&mut encoder, sym);
+    };
+
+    let mut buf = vec![0; 4];
+    BigEndian::write_u32(&mut buf, data.len() as u32);
+    buf.extend(encoder.finish());
+
+    buf
+}
+
+pub fn decode_gaps(buf: &[u8]) -> Result<Vec<u32>, String> {
+    if buf.len() < 4 {
+        return Err(String::from("Range coded block is too short"));
+    };
+
+    // As in `decode`, the length isn't trusted until the symbols are there.
+    let len = BigEndian::read_u32(&buf[0..4]) as usize;
+    let mut model = GapModel::new();
+    let mut decoder = RangeDecoder::new(&buf[4..]);
+    let mut out = Vec::with_capacity(len.min(buf.len() * 8));
+
+    for _ in 0..len {
+        out.push(model.decode(&mut decoder));
+
+        if decoder.overrun() {
+            return Err(String::from("Range coded block is truncated"));
+        };
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use huffman;
+    use mtf;
+    use range;
+    use rle;
+    use testdata;
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 4] = [b"", b"a", b"abracadabra", b"this is a test."];
+
+        for &input in inputs.iter() {
+            assert_eq!(range::decode(&range::encode(input)).unwrap(), input);
+        };
+
+        let mut skewed = vec![0; 50_000];
+        skewed.extend((0..=255).cycle().take(3000));
+        let encoded = range::encode(&skewed);
+
+        assert_eq!(range::decode(&encoded).unwrap(), skewed);
+        assert!(range::decode(&encoded[..encoded.len() / 2]).is_err());
+        assert!(range::decode(&[255, 255, 255, 255]).is_err());
+    }
+
+    #[test]
+    fn wide_round_trip() {
+        let mut data = vec![0, 1, 255, 256, 257, 1000, 0x10ffff, u32::MAX];
+        data.extend((0..5000).map(|i| (i * i) % 3000));
+        let encoded = range::encode_wide(&data);
+
+        assert_eq!(range::decode_wide(&encoded).unwrap(), data);
+        assert_eq!(range::decode_wide(&range::encode_wide(&[])).unwrap(), []);
+        assert!(range::decode_wide(&encoded[..encoded.len() / 2]).is_err());
+        assert!(range::decode_wide(&[255, 255, 255, 255]).is_err());
+    }
+
+    #[test]
+    fn gaps_round_trip() {
+        let mut data = vec![0, 1, 255, 256, 257, 1000, 0x10ffff, u32::MAX];
+        data.extend((0..5000).map(|i| (i * i) % 3000));
+        let encoded = range::encode_gaps(&data);
+
+        assert_eq!(range::decode_gaps(&encoded).unwrap(), data);
+        assert_eq!(range::decode_gaps(&range::encode_gaps(&[])).unwrap(), []);
+        assert!(range::decode_gaps(&encoded[..encoded.len() / 2]).is_err());
+        assert!(range::decode_gaps(&[255, 255, 255, 255]).is_err());
+    }
+
+    #[test]
+    fn compare_with_huffman() {
+        let data = testdata::sources();
+        let rled = rle::encode(&mtf::encode(&bwt::encode(&data)));
+
+        let mut huffed = Vec::new();
+        huffman::encode(&rled).unwrap().write(&mut huffed).unwrap();
+        let ranged = range::encode(&rled);
+
+        assert!(ranged.len() < huffed.len());
+        assert_eq!(range::decode(&ranged).unwrap(), rled);
+    }
+}
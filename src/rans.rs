@@ -0,0 +1,249 @@
+// Static rANS (range asymmetric numeral systems) with byte-wise renormalization.  The frequencies
+// from `huffman::build_freqs` are scaled to sum to `PROB_SCALE` and written ahead of the data.
+// Symbols can be spread over several interleaved coder states, which lets the decoder work on
+// them independently.
+//
+// The encoder runs backwards over the data and its output is reversed, so the decoder reads
+// forwards.  Layout: symbol count (u32 BE), number of states (u8), frequency table, then each
+// state's initial value (u32 BE) followed by the renormalization bytes.
+use huffman::build_freqs;
+use byteorder::{BigEndian, ByteOrder};
+
+const PROB_BITS: u32 = 12;
+const PROB_SCALE: u32 = 1 << PROB_BITS;
+// Lower bound of the normalized state interval [L, 256 * L).
+const RANS_L: u32 = 1 << 23;
+
+/// Scales `counts` to sum to `PROB_SCALE`, keeping every used symbol at 1 or more.  All zeroes
+/// for empty input.
+pub fn normalize_freqs(counts: &[usize; 256]) -> [u32; 256] {
+    let total: usize = counts.iter().sum();
+    let mut freqs = [0; 256];
+
+    if total == 0 {
+        return freqs;
+    };
+
+    for (freq, &count) in freqs.iter_mut().zip(counts.iter()) {
+        if count > 0 {
+            *freq = ((count as u64 * PROB_SCALE as u64 / total as u64) as u32).max(1);
+        };
+    };
+
+    // Rounding leaves the sum a little off; make it up on the most frequent symbols, which suffer
+    // least from the change.
+    loop {
+        let sum: u32 = freqs.iter().sum();
+        if sum == PROB_SCALE {
+            return freqs;
+        };
+
+        let largest = (0..256).max_by_key(|&i| (freqs[i], i)).unwrap();
+        if sum < PROB_SCALE {
+            freqs[largest] += PROB_SCALE - sum;
+        } else {
+            let spare = freqs[largest] - 1;
+            freqs[largest] -= spare.min(sum - PROB_SCALE);
+        };
+    };
+}
+
+// A bitmap of the symbols in use, then each used symbol's frequency as a u16.
+fn write_freqs(freqs: &[u32; 256], buf: &mut Vec<u8>) {
+    let mut bitmap = [0; 32];
+    for i in (0..256).filter(|&i| freqs[i] > 0) {
+        bitmap[i >> 3] |= 1 << (i & 7);
+    };
+    buf.extend_from_slice(&bitmap);
+
+    for &freq in freqs.iter().filter(|&&f| f > 0) {
+        buf.push((freq >> 8) as u8);
+        buf.push(freq as u8);
+    };
+}
+
+fn read_freqs(buf: &[u8]) -> Result<([u32; 256], usize), String> {
+    if buf.len() < 32 {
+        return Err(String::from("rANS frequency table is truncated"));
+    };
+
+    let mut freqs = [0; 256];
+    let mut pos = 32;
+
+    for (i, freq) in freqs.iter_mut().enumerate() {
+        if buf[i >> 3] & (1 << (i & 7)) == 0 {
+            continue;
+        };
+        if pos + 2 > buf.len() {
+            return Err(String::from("rANS frequency table is truncated"));
+        };
+
+        *freq = BigEndian::read_u16(&buf[pos..]) as u32;
+        pos += 2;
+    };
+
+    let sum: u32 = freqs.iter().sum();
+    if sum != 0 && sum != PROB_SCALE {
+        return Err(String::from("rANS frequencies don't add up"));
+    };
+
+    Ok((freqs, pos))
+}
+
+fn cumulative(freqs: &[u32; 256]) -> [u32; 257] {
+    let mut cum = [0; 257];
+    for i in 0..256 {
+        cum[i + 1] = cum[i] + freqs[i];
+    };
+    cum
+}
+
+/// Codes `data` using `streams` interleaved states.
+pub fn encode(data: &[u8], streams: usize) -> Vec<u8> {
+    assert!(streams > 0 && streams < 256);
+
+    let freqs = normalize_freqs(&build_freqs(data));
+    let cum = cumulative(&freqs);
+
+    let mut states = vec![RANS_L; streams];
+    let mut rev = Vec::with_capacity(data.len() / 2);
+
+    for (i, &c) in data.iter().enumerate().rev() {
+        let x = &mut states[i % streams];
+        let freq = freqs[c as usize];
+        let x_max = ((RANS_L >> PROB_BITS) << 8) * freq;
+
+        while *x >= x_max {
+            rev.push(*x as u8);
+            *x >>= 8;
+        };
+
+        *x = ((*x / freq) << PROB_BITS) + (*x % freq) + cum[c as usize];
+    };
+
+    for &x in states.iter().rev() {
+        for shift in 0..4 {
+            rev.push((x >> (8 * shift)) as u8);
+        };
+    };
+
+    let mut buf = vec![0; 5];
+    BigEndian::write_u32(&mut buf[0..4], data.len() as u32);
+    buf[4] = streams as u8;
+    write_freqs(&freqs, &mut buf);
+    buf.extend(rev.iter().rev());
+
+    buf
+}
+
+pub fn decode(buf: &[u8]) -> Result<Vec<u8>, String> {
+    if buf.len() < 5 {
+        return Err(String::from("rANS block is too short"));
+    };
+
+    let len = BigEndian::read_u32(&buf[0..4]) as usize;
+    let streams = buf[4] as usize;
+    let (freqs, table_len) = read_freqs(&buf[5..])?;
+    let cum = cumulative(&freqs);
+
+    if streams == 0 || (len > 0 && cum[256] == 0) {
+        return Err(String::from("Bad rANS header"));
+    };
+
+    let mut lookup = vec![0; PROB_SCALE as usize];
+    for sym in 0..256 {
+        for slot in cum[sym]..cum[sym + 1] {
+            lookup[slot as usize] = sym as u8;
+        };
+    };
+
+    let data = &buf[5 + table_len..];
+    if data.len() < 4 * streams {
+        return Err(String::from("rANS block is truncated"));
+    };
+
+    let mut states = data.chunks(4).take(streams).map(BigEndian::read_u32).collect::<Vec<u32>>();
+    if states.iter().any(|&x| !(RANS_L..RANS_L << 8).contains(&x)) {
+        return Err(String::from("Bad rANS state"));
+    };
+    let mut pos = 4 * streams;
+    // As in `range::decode`, the length isn't trusted until the bytes are there.
+    let mut out = Vec::with_capacity(len.min(buf.len() * 8));
+
+    for i in 0..len {
+        let x = &mut states[i % streams];
+        let slot = *x & (PROB_SCALE - 1);
+        let sym = lookup[slot as usize];
+
+        *x = freqs[sym as usize] * (*x >> PROB_BITS) + slot - cum[sym as usize];
+
+        while *x < RANS_L {
+            match data.get(pos) {
+                Some(&byte) => *x = (*x << 8) | byte as u32,
+                None => return Err(String::from("rANS block is truncated")),
+            };
+            pos += 1;
+        };
+
+        out.push(sym);
+    };
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use huffman;
+    use mtf;
+    use rans;
+    use rle;
+
+    #[test]
+    fn round_trip() {
+        let inputs: [&[u8]; 4] = [b"", b"a", b"abracadabra", b"this is a test."];
+
+        for &input in inputs.iter() {
+            for &streams in &[1, 2, 4] {
+                assert_eq!(rans::decode(&rans::encode(input, streams)).unwrap(), input);
+            };
+        };
+
+        let mut skewed = vec![0; 50_000];
+        skewed.extend((0..=255).cycle().take(3000));
+        let encoded = rans::encode(&skewed, 2);
+
+        assert_eq!(rans::decode(&encoded).unwrap(), skewed);
+        assert!(rans::decode(&encoded[..encoded.len() / 2]).is_err());
+
+        let mut huge = rans::encode(b"abc", 1);
+        huge[0..4].copy_from_slice(&[255; 4]);
+        assert!(rans::decode(&huge).is_err());
+    }
+
+    #[test]
+    fn normalize() {
+        let mut counts = [0; 256];
+        counts[0] = 1_000_000;
+        counts[1] = 1;
+        counts[2] = 3;
+
+        let freqs = rans::normalize_freqs(&counts);
+
+        assert_eq!(freqs.iter().sum::<u32>(), rans::PROB_SCALE);
+        assert_eq!(freqs[1], 1);
+        assert_eq!(freqs[2], 1);
+        assert_eq!(freqs[3], 0);
+    }
+
+    #[test]
+    fn smaller_than_huffman() {
+        let data = include_bytes!("bwt/mod.rs");
+        let rled = rle::encode(&mtf::encode(&bwt::encode(data)));
+
+        let mut huffed = Vec::new();
+        huffman::encode(&rled).unwrap().write(&mut huffed).unwrap();
+
+        assert!(rans::encode(&rled, 2).len() < huffed.len());
+    }
+}
@@ -1,77 +1,146 @@
-pub fn encode(data: &[u8]) -> Vec<u8> {
-    let mut i = 0;
-    let mut out = Vec::with_capacity(data.len());
-
-    loop {
-        if i >= data.len() {
-            break;
-        };
+use std::iter;
+
+/// `encode` for input that comes in pieces: a run can carry on from one `push` to the next, so
+/// the last run is only written by the `push` after it or by `finish`.
+#[derive(Clone, Default)]
+pub struct RleEncoder {
+    // The byte of the run so far and how long it is.
+    run: Option<(u8, usize)>,
+}
 
-        let mut count = 0 as usize;
-        let c = data[i];
+impl RleEncoder {
+    pub fn new() -> RleEncoder {
+        RleEncoder { run: None }
+    }
 
-        while i < data.len() && c == data[i] && count < 255 {
-            count += 1;
-            i += 1;
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        if let Some((c, count)) = self.run.take() {
+            out.extend(iter::repeat_n(c, count.min(4)));
+            if count >= 4 {
+                out.push(count as u8 - 4);
+            };
         };
+    }
 
-        if count < 4 {
-            for _ in 0..count {
-                out.push(c);
-            };
-        } else {
-            for _ in 0..4 {
-                out.push(c);
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &c in data {
+            match self.run {
+                Some((run, ref mut count)) if run == c && *count < 255 => *count += 1,
+                _ => {
+                    self.flush(out);
+                    self.run = Some((c, 1));
+                },
             };
+        };
+    }
+
+    /// Writes the last run.
+    pub fn finish(mut self, out: &mut Vec<u8>) {
+        self.flush(out);
+    }
+}
 
-            out.push(count as u8 - 4);
+/// The inverse of `RleEncoder`.  Input may be split anywhere, even between a run and its length.
+#[derive(Clone, Default)]
+pub struct RleDecoder {
+    // The last byte and how many times in a row it's come, up to the 4 that start a run.
+    run: Option<(u8, usize)>,
+}
+
+impl RleDecoder {
+    pub fn new() -> RleDecoder {
+        RleDecoder { run: None }
+    }
+
+    pub fn push(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        for &c in data {
+            self.run = match self.run {
+                Some((run, 4)) => {
+                    out.extend(iter::repeat_n(run, c as usize));
+                    None
+                },
+                Some((run, count)) if run == c => {
+                    out.push(c);
+                    Some((c, count + 1))
+                },
+                _ => {
+                    out.push(c);
+                    Some((c, 1))
+                },
+            };
         };
-    };
+    }
+
+    /// Ends the input, which is an error if it stopped between a run and its length.
+    pub fn finish(self, _out: &mut Vec<u8>) -> Result<(), String> {
+        match self.run {
+            Some((_, 4)) => Err(String::from("Run ends before its length")),
+            _ => Ok(()),
+        }
+    }
+}
 
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut encoder = RleEncoder::new();
+    encoder.push(data, &mut out);
+    encoder.finish(&mut out);
     out
 }
 
-pub fn decode(data: &[u8]) -> Vec<u8> {
-    let mut i = 0;
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, String> {
     let mut out = Vec::with_capacity(data.len() * 2);
+    let mut decoder = RleDecoder::new();
+    decoder.push(data, &mut out);
+    decoder.finish(&mut out)?;
 
-    loop {
-        if i >= data.len() {
-            break;
-        };
+    out.shrink_to_fit();
 
-        let mut count = 0 as usize;
-        let c = data[i];
+    Ok(out)
+}
 
-        while i < data.len() && c == data[i] && count < 4 {
-            count += 1;
-            i += 1;
-        };
+/// Like `encode`, for wider symbols.  Runs aren't split, as their lengths always fit.
+pub fn encode_symbols(data: &[u32]) -> Vec<u32> {
+    let mut out = Vec::with_capacity(data.len());
 
-        if count < 4 {
-            for _ in 0..count {
-                out.push(c);
-            };
+    for run in data.chunk_by(|a, b| a == b) {
+        if run.len() < 4 {
+            out.extend_from_slice(run);
         } else {
-            let run_count = data[i] as usize;
-            i += 1;
-
-            for _ in 0..(4 + run_count) {
-                out.push(c);
-            };
+            out.extend_from_slice(&run[..4]);
+            out.push(run.len() as u32 - 4);
         };
     };
 
-    out.shrink_to_fit();
-
     out
 }
 
+pub fn decode_symbols(data: &[u32]) -> Result<Vec<u32>, String> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+
+    while i < data.len() {
+        let c = data[i];
+        let count = data[i..].iter().take(4).take_while(|&&d| d == c).count();
+        i += count;
+
+        out.extend(iter::repeat_n(c, count));
+        if count == 4 {
+            let more = data.get(i).ok_or_else(|| String::from("Run ends before its length"))?;
+            out.extend(iter::repeat_n(c, *more as usize));
+            i += 1;
+        };
+    };
+
+    Ok(out)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn encode_test() {
         let encode_data = [1, 1, 1, 1, 1, 1, 3, 3];
         let expected_result = vec![1, 1, 1, 1, 2, 3, 3];
@@ -86,8 +155,66 @@ mod test {
         let decode_data = vec![1, 1, 1, 1, 2, 3, 3];
         let expected_result = [1, 1, 1, 1, 1, 1, 3, 3];
 
-        let decoded = decode(&decode_data);
+        let decoded = decode(&decode_data).unwrap();
 
         assert_eq!(&expected_result[0..], &decoded[0..]);
+        assert!(decode(&[1, 1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let data = [7, 7, 7, 7, 7, 7, 300, 300, 1, 1, 1, 1];
+        let expected_result = [7, 7, 7, 7, 2, 300, 300, 1, 1, 1, 1, 0];
+
+        let encoded = encode_symbols(&data);
+
+        assert_eq!(&expected_result[0..], &encoded[0..]);
+        assert_eq!(&data[0..], &decode_symbols(&encoded).unwrap()[0..]);
+        assert_eq!(decode_symbols(&encode_symbols(&[5; 1000])).unwrap(), [5; 1000]);
+        assert!(decode_symbols(&[7, 7, 7, 7]).is_err());
+    }
+
+    #[test]
+    fn streaming() {
+        let mut data = vec![1, 1, 1, 1, 1, 1, 3, 3, 9];
+        data.extend_from_slice(&[4; 600]);
+        data.extend_from_slice(b"aaaab");
+        let encoded = encode(&data);
+
+        // Splitting anywhere gives the same as one call, even inside a run or before its length.
+        for split in 0..=data.len() {
+            let mut out = Vec::new();
+            let mut encoder = RleEncoder::new();
+            encoder.push(&data[..split], &mut out);
+            encoder.push(&data[split..], &mut out);
+            encoder.finish(&mut out);
+            assert_eq!(out, encoded);
+        };
+
+        for split in 0..=encoded.len() {
+            let mut out = Vec::new();
+            let mut decoder = RleDecoder::new();
+            decoder.push(&encoded[..split], &mut out);
+            decoder.push(&encoded[split..], &mut out);
+            decoder.finish(&mut out).unwrap();
+            assert_eq!(out, data);
+        };
+
+        // Stopping before a run's length is caught, however the run was split.
+        for split in 0..=4 {
+            let mut out = Vec::new();
+            let mut decoder = RleDecoder::new();
+            decoder.push(&b"aaaa"[..split], &mut out);
+            decoder.push(&b"aaaa"[split..], &mut out);
+            assert!(decoder.finish(&mut out).is_err());
+        };
+
+        let mut out = Vec::new();
+        let mut encoder = RleEncoder::new();
+        for chunk in data.chunks(7) {
+            encoder.push(chunk, &mut out);
+        };
+        encoder.finish(&mut out);
+        assert_eq!(out, encoded);
     }
 }
@@ -0,0 +1,67 @@
+// Inputs shared by the tests: short blocks that catch edge cases, pseudo-random bytes that are
+// the same on every run, and text to measure compression on.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Blocks for round trips: empty, one byte, runs, repeats and a little text.
+pub const SAMPLES: [&[u8]; 7] = [b"", b"a", b"aaaa", b"abab", b"banana", b"this is a test.",
+                                 &[0, 255, 0, 255, 7]];
+
+/// A linear congruential generator.
+pub struct Random(u32);
+
+impl Random {
+    pub fn new(seed: u32) -> Random {
+        Random(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        self.0 >> 16
+    }
+
+    /// `len` bytes below `alphabet`.
+    pub fn bytes(&mut self, len: usize, alphabet: u32) -> Vec<u8> {
+        (0..len).map(|_| (self.next_u32() % alphabet) as u8).collect()
+    }
+}
+
+/// Some of the crate's own sources, for tests that check one coder beats another.
+pub fn sources() -> Vec<u8> {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut data = Vec::new();
+
+    for name in &["bitstream.rs", "bwt/mod.rs", "mtf.rs", "rle.rs", "range.rs"] {
+        data.extend(fs::read(src.join(name)).unwrap());
+    };
+
+    data
+}
+
+/// The files under the `CORPUS` environment variable, or `sources` if it isn't set, for the
+/// ignored comparisons.
+pub fn corpus() -> Vec<u8> {
+    fn read_all(path: &Path, data: &mut Vec<u8>) {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path).unwrap().
+                map(|entry| entry.unwrap().path()).
+                collect::<Vec<PathBuf>>();
+            entries.sort();
+            for entry in entries {
+                read_all(&entry, data);
+            };
+        } else {
+            data.extend(fs::read(path).unwrap());
+        };
+    }
+
+    match env::var_os("CORPUS") {
+        Some(path) => {
+            let mut data = Vec::new();
+            read_all(Path::new(&path), &mut data);
+            data
+        },
+        None => sources(),
+    }
+}
@@ -0,0 +1,112 @@
+// The BWT and MTF over the characters of UTF-8 text rather than its bytes, so a multi-byte
+// character keeps its contexts together.  Characters are numbered by their rank among the ones
+// the text uses, which keeps the MTF list short.  The ranks MTF gives are run-length coded, then
+// coded with `range::encode_wide`.
+//
+// The output is the characters used, as UTF-8 with its length in bytes in front, then the
+// primary index, both as big-endian u32s, then the coded ranks.
+use bwt;
+use mtf;
+use range;
+use rle;
+use std::str;
+use byteorder::{BigEndian, ByteOrder};
+
+/// Codes `text`.  Bytes that aren't UTF-8 have to be coded as bytes instead.
+pub fn encode(text: &str) -> Vec<u8> {
+    let mut alphabet = text.chars().collect::<Vec<char>>();
+    alphabet.sort_unstable();
+    alphabet.dedup();
+
+    let symbols = text.chars().
+        map(|c| alphabet.binary_search(&c).unwrap() as u32).
+        collect::<Vec<u32>>();
+    let (primary, last) = bwt::encode_symbols(&symbols, alphabet.len());
+    let ranks = rle::encode_symbols(&mtf::encode_symbols(&last, alphabet.len()));
+
+    let alphabet = alphabet.into_iter().collect::<String>();
+    let mut lengths = [0; 8];
+    BigEndian::write_u32(&mut lengths[0..4], alphabet.len() as u32);
+    BigEndian::write_u32(&mut lengths[4..8], primary as u32);
+
+    let mut buf = Vec::with_capacity(text.len() / 2);
+    buf.extend_from_slice(&lengths[0..4]);
+    buf.extend_from_slice(alphabet.as_bytes());
+    buf.extend_from_slice(&lengths[4..8]);
+    buf.extend(range::encode_wide(&ranks));
+
+    buf
+}
+
+pub fn decode(buf: &[u8]) -> Result<Vec<u8>, String> {
+    let too_short = || String::from("Unicode block is too short");
+
+    let alphabet_len = BigEndian::read_u32(buf.get(0..4).ok_or_else(too_short)?) as usize;
+    let alphabet = buf.get(4..4 + alphabet_len).ok_or_else(too_short)?;
+    let alphabet = str::from_utf8(alphabet).map_err(|err| err.to_string())?.
+        chars().
+        collect::<Vec<char>>();
+
+    let rest = &buf[4 + alphabet_len..];
+    let primary = BigEndian::read_u32(rest.get(0..4).ok_or_else(too_short)?) as usize;
+    let ranks = rle::decode_symbols(&range::decode_wide(&rest[4..])?)?;
+
+    if ranks.iter().any(|&rank| rank as usize >= alphabet.len()) {
+        return Err(String::from("Unicode block has a rank outside its alphabet"));
+    };
+    if primary >= ranks.len().max(1) {
+        return Err(String::from("Unicode block has a bad primary index"));
+    };
+
+    let last = mtf::decode_symbols(&ranks, alphabet.len());
+    let text = bwt::decode_symbols(primary, &last, alphabet.len()).
+        into_iter().
+        map(|symbol| alphabet[symbol as usize]).
+        collect::<String>();
+
+    Ok(text.into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use bwt;
+    use mtf;
+    use range;
+    use rle;
+    use testdata::Random;
+    use unicode;
+
+    #[test]
+    fn round_trip() {
+        let inputs = ["", "a", "naïve café", "ÆÆÆÆÆÆ", "日本語のテキスト、日本語の文書。",
+                      include_str!("unicode.rs")];
+
+        for &input in inputs.iter() {
+            let encoded = unicode::encode(input);
+            assert_eq!(unicode::decode(&encoded).unwrap(), input.as_bytes());
+        };
+
+        assert!(unicode::decode(&[0, 0, 0, 9, b'a']).is_err());
+    }
+
+    #[test]
+    fn compare_with_bytes() {
+        let sentences = ["Съешь же ещё этих мягких французских булок, да выпей чаю. ",
+                         "Широкая электрификация южных губерний даст мощный толчок. ",
+                         "Ξεσκεπάζω την ψυχοφθόρα βδελυγμία. ",
+                         "いろはにほへと ちりぬるを わかよたれそ つねならむ。",
+                         "Příliš žluťoučký kůň úpěl ďábelské ódy. "];
+        let text = Random::new(11).
+            bytes(2000, sentences.len() as u32).
+            into_iter().
+            map(|i| sentences[i as usize]).
+            collect::<String>();
+
+        let encoded = unicode::encode(&text);
+        let bytes = range::encode(&rle::encode(&mtf::encode(&bwt::encode(text.as_bytes())))).
+            len();
+
+        assert!(encoded.len() < bytes);
+        assert_eq!(unicode::decode(&encoded).unwrap(), text.as_bytes());
+    }
+}